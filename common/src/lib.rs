@@ -1,6 +1,14 @@
 use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
-use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::sync::watch;
+use tokio::time::{Instant, Sleep};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Model {
@@ -16,12 +24,24 @@ pub enum Command {
     /// Register a new client. Sent from frpc to frps.
     Register {
         client_id: String,
+        /// frpc version, absent for clients older than this field.
+        #[serde(default)]
+        version: Option<String>,
+        /// Most bytes per second the client wants to send through all its tunnels together.
+        #[serde(default)]
+        bandwidth_limit: Option<u64>,
     },
     /// Result of the registration. Sent from frps to frpc.
     RegisterResult {
         success: bool,
         error: Option<String>,
     },
+    /// The server is closing the control connection. Sent from frps to frpc.
+    Disconnect {
+        reason: String,
+        /// Seconds to wait before reconnecting; `None` means frpc should not reconnect.
+        reconnect_after: Option<u64>,
+    },
     /// Request a new proxy connection. Sent from frps to a chosen frpc.
     RequestNewProxyConn {
         proxy_conn_id: String,
@@ -30,6 +50,11 @@ pub enum Command {
     NewProxyConn {
         proxy_conn_id: String,
     },
+    /// The client will not open the requested proxy connection, e.g. because it is
+    /// shutting down. Sent from frpc to frps on the control connection.
+    RejectProxyConn {
+        proxy_conn_id: String,
+    },
     // Login with email and password.
     Login {
         email: String,
@@ -45,6 +70,9 @@ pub enum Command {
         error: Option<String>,
         token: Option<String>,
     },
+    /// Stop routing new connections to this client and disconnect it once its
+    /// in-flight connections finish. Sent from frpc to frps.
+    Drain,
     /// Heartbeat message from client to server
     Heartbeat {
         models: Option<Vec<Model>>,
@@ -83,17 +111,472 @@ pub async fn write_command<W: AsyncWrite + Unpin>(writer: &mut W, command: &Comm
     Ok(())
 }
 
-/// Joins two streams, copying data in both directions.
-pub async fn join_streams<A, B>(a: A, b: B) -> std::io::Result<()>
+/// Counts tunnelled connections that have not finished yet, so that a drain or
+/// shutdown can wait for them. Clones share the same count.
+#[derive(Clone)]
+pub struct InFlight(Arc<watch::Sender<usize>>);
+
+impl InFlight {
+    pub fn new() -> Self {
+        Self(Arc::new(watch::Sender::new(0)))
+    }
+
+    pub fn count(&self) -> usize {
+        *self.0.borrow()
+    }
+
+    /// Counts a connection until the returned guard is dropped.
+    pub fn start(&self) -> InFlightGuard {
+        self.0.send_modify(|count| *count += 1);
+        InFlightGuard(self.clone())
+    }
+
+    /// Resolves once no connections are in flight.
+    pub async fn wait_idle(&self) {
+        let _ = self.0.subscribe().wait_for(|count| *count == 0).await;
+    }
+
+    /// Whether both handles share one count.
+    pub fn same_as(&self, other: &InFlight) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Default for InFlight {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct InFlightGuard(InFlight);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.0.send_modify(|count| *count -= 1);
+    }
+}
+
+/// Bytes copied by `join_streams_counted` in each direction, readable while the
+/// streams are still open.
+#[derive(Default, Debug)]
+pub struct ByteCounts {
+    a_to_b: AtomicU64,
+    b_to_a: AtomicU64,
+}
+
+impl ByteCounts {
+    pub fn a_to_b(&self) -> u64 {
+        self.a_to_b.load(Ordering::Relaxed)
+    }
+
+    pub fn b_to_a(&self) -> u64 {
+        self.b_to_a.load(Ordering::Relaxed)
+    }
+}
+
+/// When bytes last moved in either direction of a join.
+struct Activity {
+    started: Instant,
+    /// Milliseconds after `started`.
+    last: AtomicU64,
+}
+
+impl Activity {
+    fn new() -> Self {
+        Self { started: Instant::now(), last: AtomicU64::new(0) }
+    }
+
+    fn touch(&self) {
+        self.last.store(self.started.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// Resolves once nothing moved for `timeout`.
+    async fn idle_for(&self, timeout: Duration) {
+        loop {
+            let deadline = self.started + Duration::from_millis(self.last.load(Ordering::Relaxed)) + timeout;
+            if Instant::now() >= deadline {
+                return;
+            }
+            tokio::time::sleep_until(deadline).await;
+        }
+    }
+}
+
+/// Adds the bytes read from `inner` to `count`.
+struct CountingReader<'a, R> {
+    inner: R,
+    count: &'a AtomicU64,
+    activity: &'a Activity,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for CountingReader<'_, R> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        let before = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        let read = buf.filled().len() - before;
+        if read > 0 {
+            self.count.fetch_add(read as u64, Ordering::Relaxed);
+            self.activity.touch();
+        }
+        poll
+    }
+}
+
+/// Bytes a throttled stream may read on credit once its tokens run out, so a
+/// slow limit sends reasonably sized chunks instead of a byte at a time.
+const THROTTLE_CHUNK: f64 = 16.0 * 1024.0;
+
+/// Token bucket refilled at `bytes_per_sec`, holding at most one second's worth
+/// of bytes. Streams sharing one limiter share its rate.
+#[derive(Debug)]
+pub struct RateLimiter {
+    bytes_per_sec: u64,
+    /// Available tokens, negative while a read taken on credit is repaid, and when they were last refilled.
+    bucket: std::sync::Mutex<(f64, Instant)>,
+}
+
+impl RateLimiter {
+    /// A rate of 0 does not limit.
+    pub fn new(bytes_per_sec: u64) -> Self {
+        Self { bytes_per_sec, bucket: std::sync::Mutex::new((bytes_per_sec as f64, Instant::now())) }
+    }
+
+    pub fn bytes_per_sec(&self) -> u64 {
+        self.bytes_per_sec
+    }
+
+    fn available(&self) -> f64 {
+        if self.bytes_per_sec == 0 {
+            return f64::INFINITY;
+        }
+        let mut bucket = self.bucket.lock().unwrap();
+        let now = Instant::now();
+        let rate = self.bytes_per_sec as f64;
+        bucket.0 = (bucket.0 + now.duration_since(bucket.1).as_secs_f64() * rate).min(rate);
+        bucket.1 = now;
+        bucket.0
+    }
+
+    fn consume(&self, bytes: usize) {
+        self.bucket.lock().unwrap().0 -= bytes as f64;
+    }
+
+    /// Most bytes a read may take given `available` tokens: all of them, or a
+    /// chunk on credit when fewer are left.
+    fn allowance(&self, available: f64) -> f64 {
+        available.max(THROTTLE_CHUNK.min(self.bytes_per_sec as f64))
+    }
+
+    /// Time until a bucket with `available` tokens is out of debt.
+    fn repay_time(&self, available: f64) -> Duration {
+        Duration::from_secs_f64((-available).max(0.0) / self.bytes_per_sec as f64)
+    }
+}
+
+/// Rate limits applied by `join_streams_with` to each direction. Every
+/// limiter listed for a direction applies, so the slowest one sets the rate.
+#[derive(Default, Clone, Debug)]
+pub struct Throttle {
+    pub a_to_b: Vec<Arc<RateLimiter>>,
+    pub b_to_a: Vec<Arc<RateLimiter>>,
+}
+
+/// Reads from `inner` no faster than its limiters allow.
+struct ThrottledReader<'a, R> {
+    inner: R,
+    limiters: &'a [Arc<RateLimiter>],
+    delay: Option<Pin<Box<Sleep>>>,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for ThrottledReader<'_, R> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        let this = &mut *self;
+        if this.limiters.is_empty() {
+            return Pin::new(&mut this.inner).poll_read(cx, buf);
+        }
+        let allowed = loop {
+            if let Some(delay) = this.delay.as_mut() {
+                ready!(delay.as_mut().poll(cx));
+                this.delay = None;
+            }
+            // Reads are paid for afterwards, so the last bytes and EOF are not
+            // held back waiting for tokens nobody needs.
+            let available: Vec<f64> = this.limiters.iter().map(|limiter| limiter.available()).collect();
+            if available.iter().all(|available| *available > -1.0) {
+                break this.limiters.iter().zip(&available)
+                    .map(|(limiter, available)| limiter.allowance(*available))
+                    .fold(f64::INFINITY, f64::min);
+            }
+            let wait = this.limiters.iter().zip(&available)
+                .map(|(limiter, available)| limiter.repay_time(*available))
+                .max()
+                .unwrap_or_default();
+            this.delay = Some(Box::pin(tokio::time::sleep(wait)));
+        };
+
+        let allowed = if allowed >= buf.remaining() as f64 { buf.remaining() } else { allowed as usize };
+        let mut limited = ReadBuf::new(buf.initialize_unfilled_to(allowed));
+        ready!(Pin::new(&mut this.inner).poll_read(cx, &mut limited))?;
+        let read = limited.filled().len();
+        for limiter in this.limiters {
+            limiter.consume(read);
+        }
+        buf.advance(read);
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Options of `join_streams_with`.
+#[derive(Default, Clone, Debug)]
+pub struct JoinOptions {
+    pub throttle: Throttle,
+    /// Close both streams once no bytes moved in either direction for this long.
+    pub idle_timeout: Option<Duration>,
+}
+
+/// Why a join of two streams ended.
+#[derive(Debug)]
+pub enum CloseReason {
+    /// Both directions reached EOF, each after shutting down the other side's write half.
+    Completed,
+    /// Nothing moved for the idle timeout.
+    IdleTimeout,
+    /// Reading or writing failed in one direction; the other one was abandoned.
+    Error(std::io::Error),
+}
+
+impl CloseReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CloseReason::Completed => "completed",
+            CloseReason::IdleTimeout => "idle_timeout",
+            CloseReason::Error(_) => "error",
+        }
+    }
+}
+
+/// Result of joining two streams: the bytes copied in each direction and why it ended.
+#[derive(Debug)]
+pub struct JoinOutcome {
+    pub a_to_b: u64,
+    pub b_to_a: u64,
+    pub reason: CloseReason,
+}
+
+/// Copies between `a` and `b` in both directions until both reach EOF. When one
+/// side stops sending, the other side's write half is shut down so it sees EOF
+/// too, while the opposite direction keeps flowing.
+pub async fn join_streams<A, B>(a: A, b: B) -> JoinOutcome
+where
+    A: AsyncRead + AsyncWrite + Unpin,
+    B: AsyncRead + AsyncWrite + Unpin,
+{
+    join_streams_counted(a, b, &ByteCounts::default()).await
+}
+
+/// Like `join_streams`, counting the bytes copied in each direction into `counts`.
+pub async fn join_streams_counted<A, B>(a: A, b: B, counts: &ByteCounts) -> JoinOutcome
 where
     A: AsyncRead + AsyncWrite + Unpin,
     B: AsyncRead + AsyncWrite + Unpin,
 {
-    let (mut a_reader, mut a_writer) = tokio::io::split(a);
-    let (mut b_reader, mut b_writer) = tokio::io::split(b);
-    tokio::select! {
-        res = tokio::io::copy(&mut a_reader, &mut b_writer) => res?,
-        res = tokio::io::copy(&mut b_reader, &mut a_writer) => res?,
+    join_streams_with(a, b, counts, &JoinOptions::default()).await
+}
+
+/// Like `join_streams_counted`, throttled and closed when idle as `options` say.
+pub async fn join_streams_with<A, B>(a: A, b: B, counts: &ByteCounts, options: &JoinOptions) -> JoinOutcome
+where
+    A: AsyncRead + AsyncWrite + Unpin,
+    B: AsyncRead + AsyncWrite + Unpin,
+{
+    let activity = Activity::new();
+    let (a_reader, mut a_writer) = tokio::io::split(a);
+    let (b_reader, mut b_writer) = tokio::io::split(b);
+    let a_reader = ThrottledReader { inner: a_reader, limiters: &options.throttle.a_to_b, delay: None };
+    let b_reader = ThrottledReader { inner: b_reader, limiters: &options.throttle.b_to_a, delay: None };
+    let mut a_reader = CountingReader { inner: a_reader, count: &counts.a_to_b, activity: &activity };
+    let mut b_reader = CountingReader { inner: b_reader, count: &counts.b_to_a, activity: &activity };
+
+    let idle = async {
+        match options.idle_timeout {
+            Some(timeout) => activity.idle_for(timeout).await,
+            None => std::future::pending().await,
+        }
+    };
+    let reason = tokio::select! {
+        res = async { tokio::try_join!(copy_half(&mut a_reader, &mut b_writer), copy_half(&mut b_reader, &mut a_writer)) } => match res {
+            Ok(_) => CloseReason::Completed,
+            Err(e) => CloseReason::Error(e),
+        },
+        _ = idle => CloseReason::IdleTimeout,
     };
+    JoinOutcome { a_to_b: counts.a_to_b(), b_to_a: counts.b_to_a(), reason }
+}
+
+/// Copies one direction until EOF, then shuts down `writer` to pass the EOF on.
+async fn copy_half<R, W>(reader: &mut R, writer: &mut W) -> std::io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    tokio::io::copy(reader, writer).await?;
+    // Fails when the peer already closed the connection, which leaves nothing to tell it
+    let _ = writer.shutdown().await;
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Copies `bytes` through a reader throttled by `limiters` and returns how long it took.
+    async fn throttled_copy(limiters: &[Arc<RateLimiter>], bytes: usize) -> Duration {
+        let (mut writer, reader) = tokio::io::duplex(64 * 1024);
+        tokio::spawn(async move { writer.write_all(&vec![0; bytes]).await.unwrap() });
+        let mut reader = ThrottledReader { inner: reader, limiters, delay: None };
+        let started = Instant::now();
+        let copied = tokio::io::copy(&mut reader, &mut tokio::io::sink()).await.unwrap();
+        assert_eq!(copied, bytes as u64);
+        started.elapsed()
+    }
+
+    fn assert_about(elapsed: Duration, expected_secs: f64) {
+        let secs = elapsed.as_secs_f64();
+        assert!((secs - expected_secs).abs() < 0.05, "took {:.3}s, expected {:.3}s", secs, expected_secs);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn limiter_sends_a_burst_then_refills_at_its_rate() {
+        let limiter = Arc::new(RateLimiter::new(10_000));
+        // The first second's worth goes out at once, the remaining 20 000 bytes take 2s
+        assert_about(throttled_copy(&[limiter], 30_000).await, 2.0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn idle_limiter_holds_at_most_one_seconds_worth() {
+        let limiters = [Arc::new(RateLimiter::new(10_000))];
+        assert_about(throttled_copy(&limiters, 10_000).await, 0.0);
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        assert_about(throttled_copy(&limiters, 30_000).await, 2.0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reads_past_the_burst_take_a_chunk_on_credit() {
+        let limiter = Arc::new(RateLimiter::new(100_000));
+        let limiters = [limiter];
+        let (mut writer, reader) = tokio::io::duplex(256 * 1024);
+        writer.write_all(&vec![0; 200_000]).await.unwrap();
+        let mut reader = ThrottledReader { inner: reader, limiters: &limiters, delay: None };
+        let mut buf = vec![0; 64 * 1024];
+        let mut burst = 0;
+        while burst < 100_000 {
+            burst += reader.read(&mut buf).await.unwrap();
+        }
+        for _ in 0..4 {
+            let read = reader.read(&mut buf).await.unwrap();
+            // Timers fire on millisecond boundaries, so a read may get up to 1ms of extra tokens
+            assert!((THROTTLE_CHUNK as usize..=THROTTLE_CHUNK as usize + 100).contains(&read), "read {} bytes", read);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn zero_rate_does_not_limit() {
+        let limiter = Arc::new(RateLimiter::new(0));
+        assert_about(throttled_copy(&[limiter], 1_000_000).await, 0.0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn slowest_limiter_sets_the_rate() {
+        let fast = Arc::new(RateLimiter::new(100_000));
+        let slow = Arc::new(RateLimiter::new(10_000));
+        assert_about(throttled_copy(&[fast, slow], 30_000).await, 2.0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn join_throttles_each_direction_separately() {
+        let (mut user, user_side) = tokio::io::duplex(64 * 1024);
+        let (service_side, mut service) = tokio::io::duplex(64 * 1024);
+        let options = JoinOptions {
+            throttle: Throttle { a_to_b: Vec::new(), b_to_a: vec![Arc::new(RateLimiter::new(10_000))] },
+            idle_timeout: None,
+        };
+        let join = tokio::spawn(async move { join_streams_with(user_side, service_side, &ByteCounts::default(), &options).await });
+
+        let started = Instant::now();
+        user.write_all(&[1; 30_000]).await.unwrap();
+        user.shutdown().await.unwrap();
+        let mut request = Vec::new();
+        service.read_to_end(&mut request).await.unwrap();
+        assert_eq!(request.len(), 30_000);
+        assert_about(started.elapsed(), 0.0);
+
+        service.write_all(&[2; 30_000]).await.unwrap();
+        service.shutdown().await.unwrap();
+        let mut response = Vec::new();
+        user.read_to_end(&mut response).await.unwrap();
+        assert_eq!(response.len(), 30_000);
+        assert_about(started.elapsed(), 2.0);
+
+        let outcome = join.await.unwrap();
+        assert!(matches!(outcome.reason, CloseReason::Completed));
+    }
+
+    #[tokio::test]
+    async fn half_closed_side_still_receives_data() {
+        let (mut user, user_side) = tokio::io::duplex(1024);
+        let (service_side, mut service) = tokio::io::duplex(1024);
+        let join = tokio::spawn(join_streams(user_side, service_side));
+
+        user.write_all(b"request").await.unwrap();
+        user.shutdown().await.unwrap();
+        let mut request = Vec::new();
+        service.read_to_end(&mut request).await.unwrap();
+        assert_eq!(request, b"request");
+
+        // The service only answers after seeing EOF, and the user still gets the answer
+        service.write_all(b"response").await.unwrap();
+        service.shutdown().await.unwrap();
+        let mut response = Vec::new();
+        user.read_to_end(&mut response).await.unwrap();
+        assert_eq!(response, b"response");
+
+        let outcome = join.await.unwrap();
+        assert!(matches!(outcome.reason, CloseReason::Completed));
+        assert_eq!((outcome.a_to_b, outcome.b_to_a), (7, 8));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn idle_join_closes_after_the_timeout() {
+        let (mut user, user_side) = tokio::io::duplex(1024);
+        let (service_side, mut service) = tokio::io::duplex(1024);
+        let options = JoinOptions { throttle: Throttle::default(), idle_timeout: Some(Duration::from_secs(30)) };
+        let started = Instant::now();
+        let join = tokio::spawn(async move { join_streams_with(user_side, service_side, &ByteCounts::default(), &options).await });
+
+        // Data moving pushes the deadline back
+        tokio::time::sleep(Duration::from_secs(20)).await;
+        user.write_all(b"ping").await.unwrap();
+        let mut ping = [0; 4];
+        service.read_exact(&mut ping).await.unwrap();
+
+        let outcome = join.await.unwrap();
+        assert!(matches!(outcome.reason, CloseReason::IdleTimeout));
+        assert_about(started.elapsed(), 50.0);
+    }
+
+    #[tokio::test]
+    async fn failed_write_ends_the_join_with_an_error() {
+        let (mut user, user_side) = tokio::io::duplex(1024);
+        let (service_side, service) = tokio::io::duplex(1024);
+        let join = tokio::spawn(join_streams(user_side, service_side));
+
+        drop(service);
+        user.write_all(b"request").await.unwrap();
+
+        let outcome = join.await.unwrap();
+        match outcome.reason {
+            CloseReason::Error(e) => assert_eq!(e.kind(), std::io::ErrorKind::BrokenPipe),
+            reason => panic!("join ended with {:?}", reason),
+        }
+    }
+}
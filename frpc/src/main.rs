@@ -1,13 +1,16 @@
 use anyhow::{anyhow, Result};
 use clap::Parser;
-use common::{read_command, write_command, join_streams, Command, Model};
+use common::{read_command, write_command, join_streams, CloseReason, Command, InFlight, Model};
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::io::{self, Write};
 use std::path::Path;
+use std::sync::Arc;
 use std::time::Duration;
+use tokio::io::AsyncWriteExt;
 use tokio::net::TcpStream;
-use tokio::time::interval;
+use tokio::sync::{watch, Mutex};
+use tokio::time::{interval, Instant};
 use tracing::{info, error, warn, Level};
 
 fn get_computer_name() -> String {
@@ -62,8 +65,20 @@ struct Args {
     /// Password for authentication (skip interactive input)
     #[arg(long)]
     password: Option<String>,
+
+    /// On SIGINT or SIGTERM, or when the server disconnects us for good, how long to let active
+    /// proxy connections finish before exiting.
+    #[arg(long, default_value_t = 30)]
+    shutdown_timeout_secs: u64,
+
+    /// Most bytes per second this machine sends through all its tunnels together, enforced by frps (0 is unlimited).
+    #[arg(long, default_value_t = 0)]
+    bandwidth_limit: u64,
 }
 
+/// Set to the deadline for active proxy connections once a shutdown is requested.
+type ShutdownDeadline = watch::Receiver<Option<Instant>>;
+
 #[derive(Serialize, Deserialize)]
 struct TokenData {
     token: String,
@@ -123,13 +138,84 @@ async fn main() -> Result<()> {
     info!("Server address: {}:{}", args.server_addr, args.control_port);
     info!("Local service: {}:{}", args.local_addr, args.local_port);
 
+    let in_flight = InFlight::new();
+    let (shutdown_tx, mut shutdown) = watch::channel(None);
+    tokio::spawn(listen_for_shutdown(shutdown_tx, Duration::from_secs(args.shutdown_timeout_secs)));
+
+    let mut reconnect_after = run_session(&args, &client_id, &in_flight, &shutdown).await?;
+    while let Some(delay) = reconnect_after {
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = shutdown_requested(&mut shutdown) => break,
+        }
+        // The server asked us to come back, e.g. while it restarts; keep trying until it is up
+        reconnect_after = match run_session(&args, &client_id, &in_flight, &shutdown).await {
+            Ok(reconnect_after) => reconnect_after,
+            Err(e) => {
+                let delay = delay.max(Duration::from_secs(1));
+                warn!("Reconnect failed: {}. Retrying in {}s.", e, delay.as_secs());
+                Some(delay)
+            }
+        };
+    }
+
+    // Proxy connections outlive the control connection, so they get the same time
+    // to finish when the server ended the session as after a signal
+    let deadline = shutdown.borrow().unwrap_or_else(|| Instant::now() + Duration::from_secs(args.shutdown_timeout_secs));
+    if tokio::time::timeout_at(deadline, in_flight.wait_idle()).await.is_err() {
+        warn!("Shutdown timeout reached, closing {} active proxy connections.", in_flight.count());
+    }
+    info!("Shutdown complete.");
+
+    Ok(())
+}
+
+/// Resolves on SIGINT, or on SIGTERM on Unix.
+async fn shutdown_signal() -> Result<()> {
+    #[cfg(unix)]
+    {
+        let mut sigterm = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+        tokio::select! {
+            res = tokio::signal::ctrl_c() => res?,
+            _ = sigterm.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await?;
+    Ok(())
+}
+
+/// Starts a graceful shutdown on the first signal and exits immediately on the second.
+async fn listen_for_shutdown(shutdown: watch::Sender<Option<Instant>>, timeout: Duration) {
+    if let Err(e) = shutdown_signal().await {
+        warn!("Could not listen for shutdown signals: {}", e);
+        return;
+    }
+    info!("Shutting down: letting active proxy connections finish for up to {}s. Signal again to exit now.", timeout.as_secs());
+    shutdown.send_replace(Some(Instant::now() + timeout));
+    if shutdown_signal().await.is_ok() {
+        warn!("Exiting without waiting for active proxy connections.");
+        std::process::exit(1);
+    }
+}
+
+/// Resolves with the deadline for active proxy connections once a shutdown is requested.
+async fn shutdown_requested(shutdown: &mut ShutdownDeadline) -> Option<Instant> {
+    shutdown.wait_for(Option::is_some).await.ok().and_then(|deadline| *deadline)
+}
+
+/// Connects, logs in, registers and serves proxy requests until the control
+/// connection ends. Returns how long to wait before reconnecting if the server
+/// asked frpc to come back later.
+async fn run_session(args: &Args, client_id: &str, in_flight: &InFlight, shutdown: &ShutdownDeadline) -> Result<Option<Duration>> {
     let control_stream = TcpStream::connect(format!("{}:{}", args.server_addr, args.control_port)).await?;
     info!("Connected to control port.");
 
     let (mut reader, mut writer) = tokio::io::split(control_stream);
 
     let token_path = Path::new("token.json");
-    if token_path.exists() {
+    let used_saved_token = token_path.exists();
+    if used_saved_token {
         let token_data: TokenData = serde_json::from_str(&fs::read_to_string(token_path)?)?;
         let login_cmd = Command::LoginByToken { token: token_data.token };
         write_command(&mut writer, &login_cmd).await?;
@@ -166,7 +252,13 @@ async fn main() -> Result<()> {
                 }
                 info!("Successfully logged in.");
             } else {
-                error!("Login failed: {}", error.unwrap_or_default());
+                let error = error.unwrap_or_default();
+                error!("Login failed: {}", error);
+                if used_saved_token && error == "Invalid token" {
+                    // The saved token expired or was revoked; log in with credentials next time
+                    fs::remove_file(token_path)?;
+                    warn!("Removed rejected token.json. Restart frpc to log in with email and password.");
+                }
                 return Err(anyhow!("Login failed"));
             }
         }
@@ -176,7 +268,11 @@ async fn main() -> Result<()> {
     }
 
     // Register the client
-    let register_cmd = Command::Register { client_id: client_id.clone() };
+    let register_cmd = Command::Register {
+        client_id: client_id.to_string(),
+        version: Some(env!("CARGO_PKG_VERSION").to_string()),
+        bandwidth_limit: Some(args.bandwidth_limit).filter(|rate| *rate > 0),
+    };
     write_command(&mut writer, &register_cmd).await?;
 
     // Wait for registration result
@@ -194,11 +290,14 @@ async fn main() -> Result<()> {
         }
     }
 
-    // Clone necessary variables for the heartbeat task
-    let mut writer_clone = writer;
+    // The heartbeat and drain tasks and proxy rejections all write to the control connection
+    let writer = Arc::new(Mutex::new(writer));
+    let writer_clone = writer.clone();
+    let logout_writer = writer.clone();
+    let reject_writer = writer.clone();
     
     // Spawn a task to send periodic heartbeats and system info
-    tokio::spawn(async move {
+    let heartbeat = tokio::spawn(async move {
         let mut interval = interval(Duration::from_secs(10)); // Send heartbeat every 10 seconds
         loop {
             interval.tick().await;
@@ -217,14 +316,14 @@ async fn main() -> Result<()> {
 
             // Send heartbeat with model info
             let heartbeat_cmd = Command::Heartbeat { models };
-            if let Err(e) = write_command(&mut writer_clone, &heartbeat_cmd).await {
+            if let Err(e) = write_command(&mut *writer_clone.lock().await, &heartbeat_cmd).await {
                 error!("Failed to send heartbeat: {}", e);
                 break;
             }
 
             // Collect and send system information
             if let Ok(sys_info) = collect_system_info().await {
-                if let Err(e) = write_command(&mut writer_clone, &Command::SystemInfo {
+                if let Err(e) = write_command(&mut *writer_clone.lock().await, &Command::SystemInfo {
                     cpu_usage: sys_info.cpu_usage,
                     memory_usage: sys_info.memory_usage,
                     disk_usage: sys_info.disk_usage,
@@ -236,35 +335,96 @@ async fn main() -> Result<()> {
             }
         }
     });
+
+    // On SIGUSR1 ask the server to drain this client: it stops routing new
+    // connections here and disconnects frpc once the in-flight ones finish.
+    #[cfg(unix)]
+    let drain = tokio::spawn(async move {
+        let mut usr1 = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::user_defined1()) {
+            Ok(usr1) => usr1,
+            Err(e) => {
+                warn!("Could not listen for SIGUSR1, drain via signal is unavailable: {}", e);
+                return;
+            }
+        };
+        while usr1.recv().await.is_some() {
+            info!("Received SIGUSR1, asking the server to drain this client.");
+            if let Err(e) = write_command(&mut *writer.lock().await, &Command::Drain).await {
+                error!("Failed to send drain request: {}", e);
+                break;
+            }
+        }
+    });
+
+    // On shutdown ask the server to stop routing here, then log out once the
+    // active proxy connections finish. The server usually disconnects us first.
+    let mut shutdown_rx = shutdown.clone();
+    let in_flight_clone = in_flight.clone();
+    let logout = tokio::spawn(async move {
+        let Some(deadline) = shutdown_requested(&mut shutdown_rx).await else {
+            return;
+        };
+        if let Err(e) = write_command(&mut *logout_writer.lock().await, &Command::Drain).await {
+            error!("Failed to send drain request: {}", e);
+        }
+        let _ = tokio::time::timeout_at(deadline, in_flight_clone.wait_idle()).await;
+        info!("Logging out.");
+        let _ = logout_writer.lock().await.shutdown().await;
+    });
     
     // Main loop to listen for commands from the server
-    loop {
+    let reconnect_after = loop {
         match read_command(&mut reader).await {
             Ok(Command::RequestNewProxyConn { proxy_conn_id }) => {
+                if shutdown.borrow().is_some() {
+                    warn!("Shutting down, refusing proxy connection {}.", proxy_conn_id);
+                    // Lets frps answer the waiting user instead of holding the connection
+                    if let Err(e) = write_command(&mut *reject_writer.lock().await, &Command::RejectProxyConn { proxy_conn_id }).await {
+                        error!("Failed to reject proxy connection: {}", e);
+                    }
+                    continue;
+                }
                 info!("Received request for new proxy connection: {}", proxy_conn_id);
                 let args_clone = args.clone();
-                let client_id_clone = client_id.clone();
+                let client_id_clone = client_id.to_string();
+                let in_flight_guard = in_flight.start();
                 tokio::spawn(async move {
+                    let _in_flight = in_flight_guard;
                     if let Err(e) = create_proxy_connection(args_clone, client_id_clone, proxy_conn_id).await {
                         error!("Failed to create proxy connection: {}", e);
                     }
                 });
             }
+            Ok(Command::Disconnect { reason, reconnect_after }) => {
+                match reconnect_after {
+                    Some(secs) => warn!("Disconnected by server: {}. Reconnecting in {}s.", reason, secs),
+                    None => error!("Disconnected by server: {}. Shutting down.", reason),
+                }
+                break reconnect_after.map(Duration::from_secs);
+            }
             Ok(cmd) => {
                 warn!("Received unexpected command: {:?}", cmd);
             }
+            Err(_) if shutdown.borrow().is_some() => {
+                info!("Logged out.");
+                break None;
+            }
             Err(ref e) if e.downcast_ref::<io::Error>().is_some_and(|io_err| io_err.kind() == io::ErrorKind::UnexpectedEof) => {
                 error!("Control connection closed by server. Shutting down.");
-                break;
+                break None;
             }
             Err(e) => {
                 error!("Error reading from control connection: {}. Shutting down.", e);
-                break;
+                break None;
             }
         }
-    }
+    };
 
-    Ok(())
+    heartbeat.abort();
+    logout.abort();
+    #[cfg(unix)]
+    drain.abort();
+    Ok(reconnect_after)
 }
 
 async fn create_proxy_connection(args: Args, _client_id: String, proxy_conn_id: String) -> Result<()> {
@@ -279,8 +439,11 @@ async fn create_proxy_connection(args: Args, _client_id: String, proxy_conn_id:
     info!("('{}') Connected to local service at {}:{}", proxy_conn_id, args.local_addr, args.local_port);
 
     info!("('{}') Joining streams...", proxy_conn_id);
-    join_streams(proxy_stream, local_stream).await?;
-    info!("('{}') Streams joined and finished.", proxy_conn_id);
+    let outcome = join_streams(proxy_stream, local_stream).await;
+    if let CloseReason::Error(e) = outcome.reason {
+        return Err(e.into());
+    }
+    info!("('{}') Streams joined and finished: {} bytes to the local service, {} bytes back.", proxy_conn_id, outcome.a_to_b, outcome.b_to_a);
 
     Ok(())
 }
@@ -459,4 +622,54 @@ async fn collect_system_info() -> Result<SystemInfo> {
         disk_usage: 0.0,
         computer_name: get_computer_name(),
     })
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    /// Reads the next command from frpc that is not a periodic report.
+    async fn next_command(frps: &mut TcpStream) -> Command {
+        loop {
+            match read_command(frps).await.unwrap() {
+                Command::Heartbeat { .. } | Command::SystemInfo { .. } => continue,
+                command => return command,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn shutdown_drains_and_rejects_new_proxy_connections() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port().to_string();
+        let args = Args::parse_from(["frpc", "--control-port", &port, "--email", "a@b.c", "--password", "secret"]);
+        let (shutdown_tx, shutdown) = watch::channel(None);
+        let in_flight = InFlight::new();
+        let generation = in_flight.start();
+        let session = tokio::spawn(async move { run_session(&args, "m1", &in_flight, &shutdown).await });
+
+        let (mut frps, _) = listener.accept().await.unwrap();
+        assert!(matches!(read_command(&mut frps).await.unwrap(), Command::Login { .. }));
+        write_command(&mut frps, &Command::LoginResult { success: true, error: None, token: None }).await.unwrap();
+        assert!(matches!(read_command(&mut frps).await.unwrap(), Command::Register { .. }));
+        write_command(&mut frps, &Command::RegisterResult { success: true, error: None }).await.unwrap();
+
+        shutdown_tx.send_replace(Some(Instant::now() + Duration::from_secs(5)));
+        assert!(matches!(next_command(&mut frps).await, Command::Drain));
+        write_command(&mut frps, &Command::RequestNewProxyConn { proxy_conn_id: "p1".to_string() }).await.unwrap();
+        assert!(matches!(next_command(&mut frps).await, Command::RejectProxyConn { proxy_conn_id } if proxy_conn_id == "p1"));
+
+        // frpc logs out once its last proxy connection finishes
+        drop(generation);
+        loop {
+            match read_command(&mut frps).await {
+                Ok(Command::Heartbeat { .. } | Command::SystemInfo { .. }) => continue,
+                Ok(command) => panic!("unexpected command {:?}", command),
+                Err(_) => break,
+            }
+        }
+        drop(frps);
+        assert_eq!(session.await.unwrap().unwrap(), None);
+    }
+}
@@ -0,0 +1,64 @@
+-- Tables used by frps. Everything is created only if missing, so databases set
+-- up by hand before migrations existed keep their tables; 0002 adds the columns
+-- those tables lack.
+
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_type WHERE typname = 'gpu_asset_status') THEN
+        CREATE TYPE gpu_asset_status AS ENUM ('online', 'offline');
+    END IF;
+END
+$$;
+
+CREATE TABLE IF NOT EXISTS "public"."api_keys" (
+    id VARCHAR PRIMARY KEY DEFAULT gen_random_uuid()::text,
+    key VARCHAR,
+    "keyHash" VARCHAR UNIQUE,
+    "keyPrefix" VARCHAR,
+    "userId" VARCHAR NOT NULL,
+    name VARCHAR,
+    status VARCHAR DEFAULT 'active',
+    "expiresAt" TIMESTAMP,
+    "createdAt" TIMESTAMP DEFAULT NOW(),
+    "updatedAt" TIMESTAMP DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS "public"."gpu_assets" (
+    "userId" VARCHAR,
+    "machineId" VARCHAR PRIMARY KEY,
+    name VARCHAR,
+    status gpu_asset_status DEFAULT 'online',
+    "createdAt" TIMESTAMP DEFAULT NOW(),
+    "updatedAt" TIMESTAMP DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS "public"."users" (
+    id VARCHAR PRIMARY KEY,
+    email VARCHAR UNIQUE NOT NULL,
+    "passwordHash" VARCHAR NOT NULL,
+    status VARCHAR NOT NULL DEFAULT 'active',
+    "failedLoginAttempts" INTEGER NOT NULL DEFAULT 0,
+    "createdAt" TIMESTAMP DEFAULT NOW(),
+    "updatedAt" TIMESTAMP DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS "public"."client_tokens" (
+    id VARCHAR PRIMARY KEY,
+    token VARCHAR,
+    "tokenHash" VARCHAR UNIQUE,
+    "tokenPrefix" VARCHAR,
+    "userId" VARCHAR NOT NULL REFERENCES "public"."users"(id),
+    "expiresAt" TIMESTAMPTZ NOT NULL,
+    "revokedAt" TIMESTAMPTZ,
+    "createdAt" TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS "public"."admin_audit_log" (
+    id BIGSERIAL PRIMARY KEY,
+    principal VARCHAR NOT NULL,
+    role VARCHAR NOT NULL,
+    method VARCHAR NOT NULL,
+    path VARCHAR NOT NULL,
+    status INTEGER NOT NULL,
+    "createdAt" TIMESTAMPTZ DEFAULT NOW()
+);
@@ -0,0 +1,35 @@
+-- Brings tables created before keys were hashed up to date: the plaintext
+-- columns become optional and the hash and display prefix columns are added.
+-- Run frps once with --hash-legacy-keys afterwards to hash the existing rows.
+
+-- The api_keys table of the original setup guide only had the key, its status
+-- and timestamps. Existing keys get an id and an empty owner, so they keep
+-- working without owner routing until they are reassigned or replaced.
+ALTER TABLE "public"."api_keys" ADD COLUMN IF NOT EXISTS id VARCHAR DEFAULT gen_random_uuid()::text;
+UPDATE "public"."api_keys" SET id = gen_random_uuid()::text WHERE id IS NULL;
+ALTER TABLE "public"."api_keys" ADD COLUMN IF NOT EXISTS "userId" VARCHAR;
+UPDATE "public"."api_keys" SET "userId" = '' WHERE "userId" IS NULL;
+ALTER TABLE "public"."api_keys" ALTER COLUMN "userId" SET NOT NULL;
+ALTER TABLE "public"."api_keys" ADD COLUMN IF NOT EXISTS name VARCHAR;
+
+DO $$
+BEGIN
+    IF NOT EXISTS (
+        SELECT 1
+        FROM pg_index i
+        JOIN pg_attribute a ON a.attrelid = i.indrelid AND a.attnum = ANY(i.indkey)
+        WHERE i.indrelid = '"public"."api_keys"'::regclass AND i.indisprimary AND a.attname = 'id'
+    ) THEN
+        ALTER TABLE "public"."api_keys" DROP CONSTRAINT IF EXISTS api_keys_pkey;
+        ALTER TABLE "public"."api_keys" ADD PRIMARY KEY (id);
+    END IF;
+END
+$$;
+
+ALTER TABLE "public"."api_keys" ALTER COLUMN key DROP NOT NULL;
+ALTER TABLE "public"."api_keys" ADD COLUMN IF NOT EXISTS "keyHash" VARCHAR UNIQUE;
+ALTER TABLE "public"."api_keys" ADD COLUMN IF NOT EXISTS "keyPrefix" VARCHAR;
+
+ALTER TABLE "public"."client_tokens" ALTER COLUMN token DROP NOT NULL;
+ALTER TABLE "public"."client_tokens" ADD COLUMN IF NOT EXISTS "tokenHash" VARCHAR UNIQUE;
+ALTER TABLE "public"."client_tokens" ADD COLUMN IF NOT EXISTS "tokenPrefix" VARCHAR;
@@ -0,0 +1,14 @@
+-- One row per control connection of a machine, from registration to disconnect.
+
+CREATE TABLE IF NOT EXISTS "public"."client_sessions" (
+    id VARCHAR PRIMARY KEY,
+    "machineId" VARCHAR NOT NULL,
+    "userId" VARCHAR NOT NULL,
+    "remoteAddr" VARCHAR,
+    "clientVersion" VARCHAR,
+    "connectedAt" TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    "disconnectedAt" TIMESTAMPTZ,
+    "disconnectReason" VARCHAR
+);
+
+CREATE INDEX IF NOT EXISTS client_sessions_machine_idx ON "public"."client_sessions" ("machineId", "connectedAt");
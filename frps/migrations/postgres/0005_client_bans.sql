@@ -0,0 +1,8 @@
+-- Machines that may not register again until "bannedUntil".
+
+CREATE TABLE IF NOT EXISTS "public"."client_bans" (
+    "machineId" VARCHAR PRIMARY KEY,
+    "bannedUntil" TIMESTAMPTZ NOT NULL,
+    reason VARCHAR,
+    "createdAt" TIMESTAMPTZ DEFAULT NOW()
+);
@@ -0,0 +1,11 @@
+-- Cumulative proxied bytes per client ("kind" = 'client') and per API key ('api_key'),
+-- from the user's point of view: upload is user to client, download client to user.
+
+CREATE TABLE IF NOT EXISTS "public"."traffic_totals" (
+    kind VARCHAR NOT NULL,
+    "subjectId" VARCHAR NOT NULL,
+    "uploadBytes" BIGINT NOT NULL DEFAULT 0,
+    "downloadBytes" BIGINT NOT NULL DEFAULT 0,
+    "updatedAt" TIMESTAMPTZ DEFAULT NOW(),
+    PRIMARY KEY (kind, "subjectId")
+);
@@ -0,0 +1,4 @@
+-- Bandwidth tier of an API key, matched against the rates given with --tier-bandwidth.
+-- Keys without a tier are not throttled.
+
+ALTER TABLE "public"."api_keys" ADD COLUMN IF NOT EXISTS tier VARCHAR;
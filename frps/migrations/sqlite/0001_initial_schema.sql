@@ -0,0 +1,52 @@
+-- Tables used by frps. Timestamps are stored as RFC 3339 text.
+
+CREATE TABLE IF NOT EXISTS api_keys (
+    id TEXT PRIMARY KEY,
+    "keyHash" TEXT UNIQUE NOT NULL,
+    "keyPrefix" TEXT,
+    "userId" TEXT NOT NULL,
+    name TEXT,
+    status TEXT NOT NULL DEFAULT 'active',
+    "expiresAt" TEXT,
+    "createdAt" TEXT NOT NULL,
+    "updatedAt" TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS gpu_assets (
+    "machineId" TEXT PRIMARY KEY,
+    "userId" TEXT,
+    name TEXT,
+    status TEXT NOT NULL DEFAULT 'online',
+    "createdAt" TEXT NOT NULL,
+    "updatedAt" TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS users (
+    id TEXT PRIMARY KEY,
+    email TEXT UNIQUE NOT NULL,
+    "passwordHash" TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'active',
+    "failedLoginAttempts" INTEGER NOT NULL DEFAULT 0,
+    "createdAt" TEXT NOT NULL,
+    "updatedAt" TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS client_tokens (
+    id TEXT PRIMARY KEY,
+    "tokenHash" TEXT UNIQUE NOT NULL,
+    "tokenPrefix" TEXT,
+    "userId" TEXT NOT NULL REFERENCES users(id),
+    "expiresAt" TEXT NOT NULL,
+    "revokedAt" TEXT,
+    "createdAt" TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS admin_audit_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    principal TEXT NOT NULL,
+    role TEXT NOT NULL,
+    method TEXT NOT NULL,
+    path TEXT NOT NULL,
+    status INTEGER NOT NULL,
+    "createdAt" TEXT NOT NULL
+);
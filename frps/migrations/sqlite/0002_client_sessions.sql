@@ -0,0 +1,14 @@
+-- One row per control connection of a machine, from registration to disconnect.
+
+CREATE TABLE IF NOT EXISTS client_sessions (
+    id TEXT PRIMARY KEY,
+    "machineId" TEXT NOT NULL,
+    "userId" TEXT NOT NULL,
+    "remoteAddr" TEXT,
+    "clientVersion" TEXT,
+    "connectedAt" TEXT NOT NULL,
+    "disconnectedAt" TEXT,
+    "disconnectReason" TEXT
+);
+
+CREATE INDEX IF NOT EXISTS client_sessions_machine_idx ON client_sessions ("machineId", "connectedAt");
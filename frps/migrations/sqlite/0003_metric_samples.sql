@@ -0,0 +1,13 @@
+-- System metrics reported by frpc, averaged over --metrics-sample-secs.
+
+CREATE TABLE IF NOT EXISTS metric_samples (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    "machineId" TEXT NOT NULL,
+    "recordedAt" TEXT NOT NULL,
+    "cpuUsage" REAL NOT NULL,
+    "memoryUsage" REAL NOT NULL,
+    "diskUsage" REAL NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS metric_samples_machine_idx ON metric_samples ("machineId", "recordedAt");
+CREATE INDEX IF NOT EXISTS metric_samples_recorded_idx ON metric_samples ("recordedAt");
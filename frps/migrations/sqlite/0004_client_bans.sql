@@ -0,0 +1,8 @@
+-- Machines that may not register again until "bannedUntil".
+
+CREATE TABLE IF NOT EXISTS client_bans (
+    "machineId" TEXT PRIMARY KEY,
+    "bannedUntil" TEXT NOT NULL,
+    reason TEXT,
+    "createdAt" TEXT NOT NULL
+);
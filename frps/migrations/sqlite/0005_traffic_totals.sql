@@ -0,0 +1,11 @@
+-- Cumulative proxied bytes per client ("kind" = 'client') and per API key ('api_key'),
+-- from the user's point of view: upload is user to client, download client to user.
+
+CREATE TABLE IF NOT EXISTS traffic_totals (
+    kind TEXT NOT NULL,
+    "subjectId" TEXT NOT NULL,
+    "uploadBytes" INTEGER NOT NULL DEFAULT 0,
+    "downloadBytes" INTEGER NOT NULL DEFAULT 0,
+    "updatedAt" TEXT NOT NULL,
+    PRIMARY KEY (kind, "subjectId")
+);
@@ -0,0 +1,315 @@
+//! Alert rules evaluated against connected clients and live events, with
+//! notifications POSTed as JSON to webhooks.
+//!
+//! Rules are loaded from the JSON file given with `--alert-rules`. A condition
+//! has to hold for `for_secs` before the rule fires, and each incident sends one
+//! `firing` and one `resolved` notification, so flapping values do not spam the
+//! webhooks.
+
+use crate::events::{Event, EventKind, RoutingError};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+/// A model nobody serves stops counting as requested after this long without requests.
+const MODEL_REQUEST_WINDOW_SECS: i64 = 3600;
+/// A client offline this long counts as retired and stops counting as offline.
+const OFFLINE_WINDOW_SECS: i64 = 24 * 3600;
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Deserialize)]
+struct AlertConfig {
+    rules: Vec<AlertRule>,
+}
+
+#[derive(Deserialize)]
+struct AlertRule {
+    name: String,
+    #[serde(flatten)]
+    condition: Condition,
+    /// URLs that receive the notifications of this rule.
+    webhooks: Vec<String>,
+    /// Seconds the condition has to hold before the rule fires.
+    #[serde(default = "default_for_secs")]
+    for_secs: i64,
+}
+
+fn default_for_secs() -> i64 {
+    60
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "condition", rename_all = "snake_case")]
+enum Condition {
+    /// A client's control connection dropped and it has not registered again.
+    /// Clients removed on purpose (API disconnect, drain, disabled user) or banned
+    /// do not count, nor do clients gone for `OFFLINE_WINDOW_SECS`.
+    ClientOffline,
+    HeartbeatStale,
+    CpuAbove { percent: f32 },
+    MemoryAbove { percent: f32 },
+    DiskAbove { percent: f32 },
+    /// Keys requested a model no connected client serves, optionally only the listed models.
+    ModelUnavailable {
+        #[serde(default)]
+        models: Option<Vec<String>>,
+    },
+}
+
+impl Condition {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Condition::ClientOffline => "client_offline",
+            Condition::HeartbeatStale => "heartbeat_stale",
+            Condition::CpuAbove { .. } => "cpu_above",
+            Condition::MemoryAbove { .. } => "memory_above",
+            Condition::DiskAbove { .. } => "disk_above",
+            Condition::ModelUnavailable { .. } => "model_unavailable",
+        }
+    }
+}
+
+/// State of a connected client that rules are evaluated against.
+pub struct ClientStatus<'a> {
+    pub client_id: &'a str,
+    pub heartbeat_stale: bool,
+    pub cpu_usage: Option<f32>,
+    pub memory_usage: Option<f32>,
+    pub disk_usage: Option<f32>,
+    pub models: Vec<&'a str>,
+}
+
+/// What a rule matched: a client or a model.
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Subject {
+    Client(String),
+    Model(String),
+}
+
+enum AlertState {
+    Pending { since: DateTime<Utc> },
+    Firing { since: DateTime<Utc> },
+}
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum AlertStatus {
+    Firing,
+    Resolved,
+}
+
+impl AlertStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            AlertStatus::Firing => "firing",
+            AlertStatus::Resolved => "resolved",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Notification<'a> {
+    status: AlertStatus,
+    rule: &'a str,
+    condition: &'static str,
+    client_id: Option<&'a str>,
+    model: Option<&'a str>,
+    /// Usage that crossed the threshold, for the usage conditions.
+    value: Option<f32>,
+    threshold: Option<f32>,
+    started_at: DateTime<Utc>,
+    timestamp: DateTime<Utc>,
+}
+
+pub struct Alerts {
+    rules: Vec<AlertRule>,
+    states: HashMap<(usize, Subject), AlertState>,
+    /// Clients whose connection dropped, since when.
+    offline_since: HashMap<String, DateTime<Utc>>,
+    /// Models requested without a client serving them: first and latest request.
+    unserved_models: HashMap<String, (DateTime<Utc>, DateTime<Utc>)>,
+    http: reqwest::Client,
+}
+
+impl Alerts {
+    pub fn load(path: &Path) -> Result<Self> {
+        let config = std::fs::read_to_string(path).with_context(|| format!("Failed to read alert rules from {}", path.display()))?;
+        let config: AlertConfig = serde_json::from_str(&config).with_context(|| format!("Invalid alert rules in {}", path.display()))?;
+        Ok(Self {
+            rules: config.rules,
+            states: HashMap::new(),
+            offline_since: HashMap::new(),
+            unserved_models: HashMap::new(),
+            http: reqwest::Client::builder().timeout(WEBHOOK_TIMEOUT).build()?,
+        })
+    }
+
+    pub fn rule_count(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// Tracks the conditions that are only visible as events.
+    pub fn observe(&mut self, event: &Event) {
+        match &event.kind {
+            EventKind::ClientRegistered { client_id, .. } | EventKind::ClientBanned { client_id, .. } => {
+                self.offline_since.remove(client_id);
+            }
+            EventKind::ClientDisconnected { client_id, reason } => {
+                if matches!(*reason, "client_closed" | "connection_error") {
+                    self.offline_since.insert(client_id.clone(), event.timestamp);
+                }
+            }
+            EventKind::RoutingError { reason: RoutingError::NoClientForModel, model: Some(model), .. } => {
+                self.unserved_models.entry(model.clone())
+                    .and_modify(|(_, last)| *last = event.timestamp)
+                    .or_insert((event.timestamp, event.timestamp));
+            }
+            _ => {}
+        }
+    }
+
+    /// Evaluates every rule, firing and resolving alerts as their conditions change.
+    pub fn evaluate(&mut self, clients: &[ClientStatus]) {
+        let now = Utc::now();
+        self.offline_since.retain(|_, since| (now - *since).num_seconds() < OFFLINE_WINDOW_SECS);
+        self.unserved_models.retain(|model, (_, last_requested)| {
+            (now - *last_requested).num_seconds() < MODEL_REQUEST_WINDOW_SECS
+                && !clients.iter().any(|client| client.models.contains(&model.as_str()))
+        });
+
+        let mut matched: Vec<(usize, Subject, DateTime<Utc>, Option<f32>)> = Vec::new();
+        for (index, rule) in self.rules.iter().enumerate() {
+            let usage_above = |usage: fn(&ClientStatus) -> Option<f32>, threshold: f32| {
+                clients.iter()
+                    .filter_map(|client| usage(client).filter(|value| *value > threshold).map(|value| (client.client_id, value)))
+                    .map(|(client_id, value)| (index, Subject::Client(client_id.to_string()), now, Some(value)))
+                    .collect::<Vec<_>>()
+            };
+            match &rule.condition {
+                Condition::ClientOffline => matched.extend(self.offline_since.iter()
+                    .map(|(client_id, since)| (index, Subject::Client(client_id.clone()), *since, None))),
+                Condition::HeartbeatStale => matched.extend(clients.iter()
+                    .filter(|client| client.heartbeat_stale)
+                    .map(|client| (index, Subject::Client(client.client_id.to_string()), now, None))),
+                Condition::CpuAbove { percent } => matched.extend(usage_above(|client| client.cpu_usage, *percent)),
+                Condition::MemoryAbove { percent } => matched.extend(usage_above(|client| client.memory_usage, *percent)),
+                Condition::DiskAbove { percent } => matched.extend(usage_above(|client| client.disk_usage, *percent)),
+                Condition::ModelUnavailable { models } => matched.extend(self.unserved_models.iter()
+                    .filter(|(model, _)| models.as_ref().is_none_or(|models| models.contains(model)))
+                    .map(|(model, (first_requested, _))| (index, Subject::Model(model.clone()), *first_requested, None))),
+            }
+        }
+
+        let mut active = std::collections::HashSet::new();
+        for (index, subject, since, value) in matched {
+            let key = (index, subject);
+            let state = self.states.entry(key.clone()).or_insert(AlertState::Pending { since });
+            if let AlertState::Pending { since } = *state {
+                if (now - since).num_seconds() >= self.rules[index].for_secs {
+                    *state = AlertState::Firing { since };
+                    self.notify(index, &key.1, AlertStatus::Firing, since, value);
+                }
+            }
+            active.insert(key);
+        }
+
+        let ended: Vec<(usize, Subject)> = self.states.keys().filter(|key| !active.contains(*key)).cloned().collect();
+        for key in ended {
+            if let Some(AlertState::Firing { since }) = self.states.remove(&key) {
+                self.notify(key.0, &key.1, AlertStatus::Resolved, since, None);
+            }
+        }
+    }
+
+    fn notify(&self, index: usize, subject: &Subject, status: AlertStatus, started_at: DateTime<Utc>, value: Option<f32>) {
+        let rule = &self.rules[index];
+        let (client_id, model) = match subject {
+            Subject::Client(client_id) => (Some(client_id.as_str()), None),
+            Subject::Model(model) => (None, Some(model.as_str())),
+        };
+        let threshold = match rule.condition {
+            Condition::CpuAbove { percent } | Condition::MemoryAbove { percent } | Condition::DiskAbove { percent } => Some(percent),
+            _ => None,
+        };
+        let notification = Notification {
+            status,
+            rule: &rule.name,
+            condition: rule.condition.as_str(),
+            client_id,
+            model,
+            value,
+            threshold,
+            started_at,
+            timestamp: Utc::now(),
+        };
+        let body = match serde_json::to_value(&notification) {
+            Ok(body) => body,
+            Err(e) => {
+                error!("Failed to encode alert {}: {}", rule.name, e);
+                return;
+            }
+        };
+        info!("Alert {} {} for {}", rule.name, status.as_str(), client_id.or(model).unwrap_or_default());
+
+        for url in &rule.webhooks {
+            let request = self.http.post(url).json(&body);
+            let url = url.clone();
+            tokio::spawn(async move {
+                match request.send().await.and_then(|response| response.error_for_status()) {
+                    Ok(_) => {}
+                    Err(e) => warn!("Failed to deliver alert to {}: {}", url, e),
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn offline_alerts() -> Alerts {
+        let rule = AlertRule { name: "offline".to_string(), condition: Condition::ClientOffline, webhooks: Vec::new(), for_secs: 0 };
+        Alerts {
+            rules: vec![rule],
+            states: HashMap::new(),
+            offline_since: HashMap::new(),
+            unserved_models: HashMap::new(),
+            http: reqwest::Client::new(),
+        }
+    }
+
+    fn event(kind: EventKind, timestamp: DateTime<Utc>) -> Event {
+        Event { timestamp, kind }
+    }
+
+    fn firing(alerts: &Alerts) -> bool {
+        alerts.states.values().any(|state| matches!(state, AlertState::Firing { .. }))
+    }
+
+    #[test]
+    fn ban_resolves_offline_alert() {
+        let mut alerts = offline_alerts();
+        alerts.observe(&event(EventKind::ClientDisconnected { client_id: "m1".to_string(), reason: "connection_error" }, Utc::now()));
+        alerts.evaluate(&[]);
+        assert!(firing(&alerts));
+
+        alerts.observe(&event(EventKind::ClientBanned { client_id: "m1".to_string(), until: Utc::now() }, Utc::now()));
+        alerts.evaluate(&[]);
+        assert!(alerts.states.is_empty());
+    }
+
+    #[test]
+    fn long_offline_client_stops_counting() {
+        let mut alerts = offline_alerts();
+        let dropped = Utc::now() - chrono::Duration::seconds(OFFLINE_WINDOW_SECS + 1);
+        alerts.observe(&event(EventKind::ClientDisconnected { client_id: "m1".to_string(), reason: "client_closed" }, dropped));
+        alerts.evaluate(&[]);
+        assert!(alerts.states.is_empty());
+        assert!(alerts.offline_since.is_empty());
+    }
+}
@@ -0,0 +1,199 @@
+//! Cache for token validation results: a small in-process LRU in front of Redis.
+//!
+//! Redis is treated as optional. Every Redis call is bounded by a timeout and
+//! errors are logged and bypassed, so callers fall through to the database
+//! instead of failing while Redis is unreachable.
+
+use lru::LruCache;
+use redis::aio::{ConnectionManager, PubSub};
+use redis::{AsyncCommands, Client as RedisClient, RedisResult};
+use std::future::Future;
+use std::num::NonZeroUsize;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// Upper bound for a Redis round trip, so a stalled Redis cannot hold up routing.
+const REDIS_TIMEOUT: Duration = Duration::from_millis(500);
+/// Minimum time between attempts to (re)connect to an unreachable Redis.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(10);
+/// Local entries expire sooner than Redis ones, so an instance that missed an
+/// invalidation message converges quickly.
+const LOCAL_TTL: Duration = Duration::from_secs(30);
+
+pub struct Cache {
+    client: RedisClient,
+    redis: RwLock<Option<ConnectionManager>>,
+    last_connect_attempt: Mutex<Option<Instant>>,
+    /// `None` when the local layer is disabled.
+    local: Option<Mutex<LruCache<String, (String, Instant)>>>,
+}
+
+impl Cache {
+    /// Creates the cache and tries to connect to Redis once. A failed
+    /// connection is not an error; it is retried lazily on later calls.
+    pub async fn connect(redis_url: &str, local_capacity: usize) -> anyhow::Result<Self> {
+        let cache = Self {
+            client: RedisClient::open(redis_url)?,
+            redis: RwLock::new(None),
+            last_connect_attempt: Mutex::new(None),
+            local: NonZeroUsize::new(local_capacity).map(|cap| Mutex::new(LruCache::new(cap))),
+        };
+        cache.connection().await;
+        Ok(cache)
+    }
+
+    async fn connection(&self) -> Option<ConnectionManager> {
+        if let Some(conn) = self.redis.read().unwrap().clone() {
+            return Some(conn);
+        }
+
+        {
+            let mut last_attempt = self.last_connect_attempt.lock().unwrap();
+            if last_attempt.is_some_and(|t| t.elapsed() < RECONNECT_INTERVAL) {
+                return None;
+            }
+            *last_attempt = Some(Instant::now());
+        }
+
+        // The manager reconnects on its own once established
+        match tokio::time::timeout(REDIS_TIMEOUT, ConnectionManager::new(self.client.clone())).await {
+            Ok(Ok(conn)) => {
+                info!("Connected to Redis successfully");
+                *self.redis.write().unwrap() = Some(conn.clone());
+                Some(conn)
+            }
+            Ok(Err(e)) => {
+                warn!("Redis unavailable, continuing without it: {}", e);
+                None
+            }
+            Err(_) => {
+                warn!("Timed out connecting to Redis, continuing without it");
+                None
+            }
+        }
+    }
+
+    /// Runs `op` against Redis, returning `None` if Redis is unavailable, errors or times out.
+    async fn with_redis<T, F, Fut>(&self, name: &str, op: F) -> Option<T>
+    where
+        F: FnOnce(ConnectionManager) -> Fut,
+        Fut: Future<Output = RedisResult<T>>,
+    {
+        let conn = self.connection().await?;
+        match tokio::time::timeout(REDIS_TIMEOUT, op(conn)).await {
+            Ok(Ok(value)) => Some(value),
+            Ok(Err(e)) => {
+                warn!("Redis {} failed, bypassing cache: {}", name, e);
+                None
+            }
+            Err(_) => {
+                warn!("Redis {} timed out, bypassing cache", name);
+                None
+            }
+        }
+    }
+
+    fn get_local(&self, key: &str) -> Option<String> {
+        let mut local = self.local.as_ref()?.lock().unwrap();
+        match local.get(key) {
+            Some((value, expires)) if *expires > Instant::now() => Some(value.clone()),
+            Some(_) => {
+                local.pop(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn put_local(&self, key: &str, value: &str, ttl: Duration) {
+        if let Some(local) = &self.local {
+            let expires = Instant::now() + ttl.min(LOCAL_TTL);
+            local.lock().unwrap().put(key.to_string(), (value.to_string(), expires));
+        }
+    }
+
+    pub async fn get(&self, key: &str) -> Option<String> {
+        if let Some(value) = self.get_local(key) {
+            return Some(value);
+        }
+
+        let value: Option<String> = self.with_redis("GET", |mut conn| async move { conn.get(key).await }).await.flatten();
+        if let Some(value) = &value {
+            self.put_local(key, value, LOCAL_TTL);
+        }
+        value
+    }
+
+    pub async fn set(&self, key: &str, value: &str, ttl: Duration) {
+        self.put_local(key, value, ttl);
+        let seconds = ttl.as_secs().max(1);
+        let _: Option<()> = self.with_redis("SETEX", |mut conn| async move { conn.set_ex(key, value, seconds).await }).await;
+    }
+
+    /// Drops `keys` from both layers.
+    pub async fn forget(&self, keys: &[String]) {
+        if keys.is_empty() {
+            return;
+        }
+        if let Some(local) = &self.local {
+            let mut local = local.lock().unwrap();
+            for key in keys {
+                local.pop(key);
+            }
+        }
+        let _: Option<()> = self.with_redis("DEL", |mut conn| async move { conn.del(keys).await }).await;
+    }
+
+    /// Publishes `payload` on `channel`. Returns `false` if it could not be sent.
+    pub async fn publish(&self, channel: &str, payload: &str) -> bool {
+        let sent: Option<()> = self.with_redis("PUBLISH", |mut conn| async move { conn.publish(channel, payload).await }).await;
+        sent.is_some()
+    }
+
+    /// Opens a dedicated connection subscribed to `channel`.
+    pub async fn subscribe(&self, channel: &str) -> RedisResult<PubSub> {
+        let mut pubsub = self.client.get_async_connection().await?.into_pubsub();
+        pubsub.subscribe(channel).await?;
+        Ok(pubsub)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Nothing listens on port 1, so every Redis call is bypassed.
+    const UNREACHABLE_REDIS: &str = "redis://127.0.0.1:1";
+
+    #[tokio::test]
+    async fn local_layer_serves_while_redis_is_down() {
+        let cache = Cache::connect(UNREACHABLE_REDIS, 2).await.unwrap();
+        cache.set("a", "1", Duration::from_secs(300)).await;
+        assert_eq!(cache.get("a").await.as_deref(), Some("1"));
+
+        cache.forget(&["a".to_string()]).await;
+        assert_eq!(cache.get("a").await, None);
+        assert!(!cache.publish("channel", "payload").await);
+    }
+
+    #[tokio::test]
+    async fn local_layer_evicts_the_least_recently_used_entry() {
+        let cache = Cache::connect(UNREACHABLE_REDIS, 2).await.unwrap();
+        cache.set("a", "1", Duration::from_secs(300)).await;
+        cache.set("b", "2", Duration::from_secs(300)).await;
+        cache.get("a").await;
+        cache.set("c", "3", Duration::from_secs(300)).await;
+
+        assert_eq!(cache.get("a").await.as_deref(), Some("1"));
+        assert_eq!(cache.get("b").await, None);
+        assert_eq!(cache.get("c").await.as_deref(), Some("3"));
+    }
+
+    #[tokio::test]
+    async fn without_a_local_layer_nothing_is_cached() {
+        let cache = Cache::connect(UNREACHABLE_REDIS, 0).await.unwrap();
+        cache.set("a", "1", Duration::from_secs(300)).await;
+        assert_eq!(cache.get("a").await, None);
+    }
+}
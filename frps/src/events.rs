@@ -0,0 +1,142 @@
+//! Live client and routing events, streamed to API consumers at `/api/events`.
+//!
+//! Events are broadcast to every subscriber at the time they happen; nothing is
+//! stored, so a dashboard should load `/api/clients` once and then apply events.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+/// Events buffered per subscriber before a slow one starts missing events.
+const CAPACITY: usize = 1024;
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EventKind {
+    ClientRegistered {
+        client_id: String,
+        user_id: String,
+        remote_addr: String,
+        version: Option<String>,
+    },
+    ClientDisconnected {
+        client_id: String,
+        reason: &'static str,
+    },
+    /// An administrator banned the client, whether or not it was connected.
+    ClientBanned {
+        client_id: String,
+        until: DateTime<Utc>,
+    },
+    /// No heartbeat from the client within `HEARTBEAT_STALE_SECS`.
+    HeartbeatStale {
+        client_id: String,
+        last_heartbeat: DateTime<Utc>,
+    },
+    /// A client reported as stale sent a heartbeat again.
+    HeartbeatRecovered {
+        client_id: String,
+    },
+    /// The models a client serves changed; `models` is the new list of ids.
+    ModelsChanged {
+        client_id: String,
+        models: Vec<String>,
+    },
+    /// A public connection could not be routed as requested.
+    RoutingError {
+        reason: RoutingError,
+        model: Option<String>,
+        client_id: Option<String>,
+    },
+}
+
+impl EventKind {
+    /// Name used as the SSE event type, matching the serialized `type`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventKind::ClientRegistered { .. } => "client_registered",
+            EventKind::ClientDisconnected { .. } => "client_disconnected",
+            EventKind::ClientBanned { .. } => "client_banned",
+            EventKind::HeartbeatStale { .. } => "heartbeat_stale",
+            EventKind::HeartbeatRecovered { .. } => "heartbeat_recovered",
+            EventKind::ModelsChanged { .. } => "models_changed",
+            EventKind::RoutingError { .. } => "routing_error",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoutingError {
+    /// No connected client serves the requested model; the request fell back to other clients.
+    NoClientForModel,
+    /// Owner routing is required and none of the key owner's machines are connected,
+    /// or, when `model` is set, none of them serves it.
+    NoOwnedMachines,
+    /// No client is connected, or all of them are draining.
+    NoActiveClients,
+    /// The chosen client could not be asked for a proxy connection.
+    ClientUnreachable,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Event {
+    pub timestamp: DateTime<Utc>,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+/// Broadcasts events to the current subscribers. Clones share the same channel.
+#[derive(Clone)]
+pub struct Events(broadcast::Sender<Event>);
+
+impl Events {
+    pub fn new() -> Self {
+        Self(broadcast::Sender::new(CAPACITY))
+    }
+
+    pub fn emit(&self, kind: EventKind) {
+        // Fails only when nobody is subscribed
+        let _ = self.0.send(Event { timestamp: Utc::now(), kind });
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.0.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_names_match_the_serialized_type() {
+        let until = Utc::now();
+        let kinds = [
+            EventKind::ClientRegistered { client_id: "m1".to_string(), user_id: "u1".to_string(), remote_addr: "127.0.0.1:1".to_string(), version: None },
+            EventKind::ClientDisconnected { client_id: "m1".to_string(), reason: "client_closed" },
+            EventKind::ClientBanned { client_id: "m1".to_string(), until },
+            EventKind::HeartbeatStale { client_id: "m1".to_string(), last_heartbeat: until },
+            EventKind::HeartbeatRecovered { client_id: "m1".to_string() },
+            EventKind::ModelsChanged { client_id: "m1".to_string(), models: vec!["llama".to_string()] },
+            EventKind::RoutingError { reason: RoutingError::NoOwnedMachines, model: None, client_id: None },
+        ];
+        for kind in kinds {
+            let json = serde_json::to_value(Event { timestamp: until, kind: kind.clone() }).unwrap();
+            assert_eq!(json["type"], kind.name());
+            assert!(json["timestamp"].is_string());
+        }
+    }
+
+    #[tokio::test]
+    async fn subscribers_receive_events_emitted_after_subscribing() {
+        let events = Events::new();
+        events.emit(EventKind::HeartbeatRecovered { client_id: "before".to_string() });
+        let mut receiver = events.subscribe();
+        events.clone().emit(EventKind::HeartbeatRecovered { client_id: "after".to_string() });
+
+        let event = receiver.recv().await.unwrap();
+        assert!(matches!(event.kind, EventKind::HeartbeatRecovered { client_id } if client_id == "after"));
+        assert!(receiver.try_recv().is_err());
+    }
+}
@@ -0,0 +1,240 @@
+//! Prometheus metrics served at `/metrics` in the OpenMetrics text format.
+//!
+//! Counters and histograms are updated where the events happen. Gauges that
+//! mirror in-memory state (connected clients, pending connections, per-client
+//! system usage) are refreshed from that state on every scrape.
+
+use prometheus_client::encoding::{text::encode, EncodeLabelSet};
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::metrics::histogram::{exponential_buckets, Histogram};
+use prometheus_client::registry::Registry;
+use std::sync::atomic::AtomicU64;
+use std::time::Instant;
+
+pub const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+#[derive(Clone, Copy, Debug)]
+pub enum ConnectionKind {
+    Control,
+    Proxy,
+    Public,
+}
+
+impl ConnectionKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            ConnectionKind::Control => "control",
+            ConnectionKind::Proxy => "proxy",
+            ConnectionKind::Public => "public",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum AuthKind {
+    /// API key on public traffic.
+    ApiKey,
+    /// frpc login with email and password.
+    ClientLogin,
+    /// frpc login with a saved session token.
+    ClientToken,
+    /// Management API key.
+    ManagementApi,
+}
+
+impl AuthKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            AuthKind::ApiKey => "api_key",
+            AuthKind::ClientLogin => "client_login",
+            AuthKind::ClientToken => "client_token",
+            AuthKind::ManagementApi => "management_api",
+        }
+    }
+}
+
+/// How the client for a public connection was chosen, or why none was.
+#[derive(Clone, Copy, Debug)]
+pub enum RoutingDecision {
+    ClientHeader,
+    Model,
+    Owned,
+    Random,
+    Unavailable,
+}
+
+impl RoutingDecision {
+    fn as_str(self) -> &'static str {
+        match self {
+            RoutingDecision::ClientHeader => "client_header",
+            RoutingDecision::Model => "model",
+            RoutingDecision::Owned => "owned",
+            RoutingDecision::Random => "random",
+            RoutingDecision::Unavailable => "unavailable",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct ConnectionLabels {
+    kind: &'static str,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct AuthLabels {
+    kind: &'static str,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct RoutingLabels {
+    decision: &'static str,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct ClientLabels {
+    client_id: String,
+}
+
+type FloatGauge = Gauge<f64, AtomicU64>;
+
+/// System usage last reported by a connected client, in percent.
+pub struct ClientUsage<'a> {
+    pub client_id: &'a str,
+    pub cpu_usage: f32,
+    pub memory_usage: f32,
+    pub disk_usage: f32,
+}
+
+pub struct Metrics {
+    registry: Registry,
+    connections: Family<ConnectionLabels, Counter>,
+    auth_failures: Family<AuthLabels, Counter>,
+    routing_decisions: Family<RoutingLabels, Counter>,
+    proxy_setup_seconds: Histogram,
+    active_clients: Gauge,
+    pending_connections: Gauge,
+    client_cpu_usage: Family<ClientLabels, FloatGauge>,
+    client_memory_usage: Family<ClientLabels, FloatGauge>,
+    client_disk_usage: Family<ClientLabels, FloatGauge>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let mut registry = Registry::with_prefix("frps");
+        let connections = Family::<ConnectionLabels, Counter>::default();
+        let auth_failures = Family::<AuthLabels, Counter>::default();
+        let routing_decisions = Family::<RoutingLabels, Counter>::default();
+        // 1ms up to ~16s
+        let proxy_setup_seconds = Histogram::new(exponential_buckets(0.001, 2.0, 15));
+        let active_clients = Gauge::default();
+        let pending_connections = Gauge::default();
+        let client_cpu_usage = Family::<ClientLabels, FloatGauge>::default();
+        let client_memory_usage = Family::<ClientLabels, FloatGauge>::default();
+        let client_disk_usage = Family::<ClientLabels, FloatGauge>::default();
+
+        registry.register("connections", "Accepted TCP connections by listener", connections.clone());
+        registry.register("auth_failures", "Rejected authentication attempts", auth_failures.clone());
+        registry.register("routing_decisions", "How public connections were assigned to a client", routing_decisions.clone());
+        registry.register(
+            "proxy_setup_duration_seconds",
+            "Time from requesting a proxy connection from frpc until it is paired with the public connection",
+            proxy_setup_seconds.clone(),
+        );
+        registry.register("active_clients", "Registered frpc clients", active_clients.clone());
+        registry.register("pending_connections", "Public connections waiting for a proxy connection", pending_connections.clone());
+        registry.register("client_cpu_usage_percent", "CPU usage last reported by a client", client_cpu_usage.clone());
+        registry.register("client_memory_usage_percent", "Memory usage last reported by a client", client_memory_usage.clone());
+        registry.register("client_disk_usage_percent", "Disk usage last reported by a client", client_disk_usage.clone());
+
+        Self {
+            registry,
+            connections,
+            auth_failures,
+            routing_decisions,
+            proxy_setup_seconds,
+            active_clients,
+            pending_connections,
+            client_cpu_usage,
+            client_memory_usage,
+            client_disk_usage,
+        }
+    }
+
+    pub fn connection_accepted(&self, kind: ConnectionKind) {
+        self.connections.get_or_create(&ConnectionLabels { kind: kind.as_str() }).inc();
+    }
+
+    pub fn auth_failed(&self, kind: AuthKind) {
+        self.auth_failures.get_or_create(&AuthLabels { kind: kind.as_str() }).inc();
+    }
+
+    pub fn routed(&self, decision: RoutingDecision) {
+        self.routing_decisions.get_or_create(&RoutingLabels { decision: decision.as_str() }).inc();
+    }
+
+    pub fn proxy_paired(&self, requested_at: Instant) {
+        self.proxy_setup_seconds.observe(requested_at.elapsed().as_secs_f64());
+    }
+
+    /// Refreshes the state gauges and encodes every metric.
+    pub fn render<'a>(&self, active_clients: usize, pending_connections: usize, usage: impl IntoIterator<Item = ClientUsage<'a>>) -> Result<String, std::fmt::Error> {
+        self.active_clients.set(active_clients as i64);
+        self.pending_connections.set(pending_connections as i64);
+
+        // Drop series of clients that have disconnected since the last scrape
+        self.client_cpu_usage.clear();
+        self.client_memory_usage.clear();
+        self.client_disk_usage.clear();
+        for client in usage {
+            let labels = ClientLabels { client_id: client.client_id.to_string() };
+            self.client_cpu_usage.get_or_create(&labels).set(client.cpu_usage as f64);
+            self.client_memory_usage.get_or_create(&labels).set(client.memory_usage as f64);
+            self.client_disk_usage.get_or_create(&labels).set(client.disk_usage as f64);
+        }
+
+        let mut body = String::new();
+        encode(&mut body, &self.registry)?;
+        Ok(body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(client_id: &str) -> ClientUsage<'_> {
+        ClientUsage { client_id, cpu_usage: 12.5, memory_usage: 40.0, disk_usage: 70.0 }
+    }
+
+    #[test]
+    fn render_includes_counters_and_current_state() {
+        let metrics = Metrics::new();
+        metrics.connection_accepted(ConnectionKind::Public);
+        metrics.auth_failed(AuthKind::ApiKey);
+        metrics.auth_failed(AuthKind::ApiKey);
+        metrics.routed(RoutingDecision::Owned);
+
+        let body = metrics.render(1, 3, [usage("m1")]).unwrap();
+        for line in [
+            "frps_connections_total{kind=\"public\"} 1",
+            "frps_auth_failures_total{kind=\"api_key\"} 2",
+            "frps_routing_decisions_total{decision=\"owned\"} 1",
+            "frps_active_clients 1",
+            "frps_pending_connections 3",
+            "frps_client_cpu_usage_percent{client_id=\"m1\"} 12.5",
+        ] {
+            assert!(body.lines().any(|l| l == line), "missing {:?} in\n{}", line, body);
+        }
+    }
+
+    #[test]
+    fn render_drops_clients_that_disconnected() {
+        let metrics = Metrics::new();
+        metrics.render(2, 0, [usage("m1"), usage("m2")]).unwrap();
+        let body = metrics.render(1, 0, [usage("m2")]).unwrap();
+        assert!(!body.contains("client_id=\"m1\""));
+        assert!(body.contains("client_id=\"m2\""));
+    }
+}
@@ -0,0 +1,427 @@
+use super::{display_prefix, ApiKeyOwner, ApiKeySummary, ClientSession, ClientToken, ClientTokenSummary, MetricSample, NewApiKey, NewClientSession, Storage, TrafficKind, TrafficTotal, User, UserStatus, ABANDONED_SESSION};
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+/// Audit entries kept by the memory backend; older ones are dropped.
+const AUDIT_LOG_CAPACITY: usize = 1000;
+
+/// Keeps everything in process memory; state is lost on restart. Meant for
+/// local development and integration tests.
+#[derive(Default)]
+pub struct MemoryStorage {
+    state: Mutex<MemoryState>,
+}
+
+#[derive(Default)]
+struct MemoryState {
+    api_keys: HashMap<String, ApiKeySummary>,
+    users: HashMap<String, User>,
+    client_tokens: HashMap<String, StoredClientToken>,
+    machines: HashMap<String, Machine>,
+    sessions: Vec<ClientSession>,
+    metric_samples: HashMap<String, VecDeque<MetricSample>>,
+    client_bans: HashMap<String, DateTime<Utc>>,
+    traffic: HashMap<(TrafficKind, String), (u64, u64)>,
+    audit_log: VecDeque<AuditEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuditEntry {
+    pub principal: String,
+    pub role: String,
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+}
+
+struct StoredClientToken {
+    token_hash: String,
+    token_prefix: String,
+    user_id: String,
+    expires_at: DateTime<Utc>,
+    revoked_at: Option<DateTime<Utc>>,
+    created_at: DateTime<Utc>,
+}
+
+impl StoredClientToken {
+    fn is_live(&self, now: DateTime<Utc>) -> bool {
+        self.revoked_at.is_none() && self.expires_at > now
+    }
+}
+
+struct Machine {
+    user_id: String,
+    status: String,
+}
+
+impl MemoryStorage {
+    /// The most recent audit entries, oldest first.
+    #[cfg(test)]
+    pub fn audit_entries(&self) -> Vec<AuditEntry> {
+        self.state.lock().unwrap().audit_log.iter().cloned().collect()
+    }
+}
+
+impl MemoryState {
+    fn revoke_tokens(&mut self, matches: impl Fn(&str, &StoredClientToken) -> bool) -> Vec<Option<String>> {
+        let now = Utc::now();
+        self.client_tokens.iter_mut()
+            .filter(|(id, token)| token.revoked_at.is_none() && matches(id, token))
+            .map(|(_, token)| {
+                token.revoked_at = Some(now);
+                Some(token.token_hash.clone())
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn api_key_owner(&self, key_hash: &str) -> Result<Option<ApiKeyOwner>> {
+        let state = self.state.lock().unwrap();
+        let now = Utc::now();
+        Ok(state.api_keys.values()
+            .find(|key| key.key_hash.as_deref() == Some(key_hash))
+            .filter(|key| key.status == "active" && key.expires_at.is_none_or(|t| t > now))
+            .map(|key| ApiKeyOwner { key_id: key.id.clone(), user_id: key.user_id.clone(), tier: key.tier.clone() }))
+    }
+
+    async fn create_api_key(&self, key: NewApiKey<'_>) -> Result<ApiKeySummary> {
+        let summary = ApiKeySummary {
+            id: Uuid::new_v4().to_string(),
+            user_id: key.user_id.to_string(),
+            name: key.name.map(str::to_string),
+            key_prefix: display_prefix(Some(key.key_prefix.to_string())),
+            status: "active".to_string(),
+            tier: key.tier.map(str::to_string),
+            expires_at: key.expires_at,
+            created_at: Utc::now(),
+            key_hash: Some(key.key_hash.to_string()),
+        };
+        self.state.lock().unwrap().api_keys.insert(summary.id.clone(), summary.clone());
+        Ok(summary)
+    }
+
+    async fn list_api_keys(&self, user_id: Option<&str>) -> Result<Vec<ApiKeySummary>> {
+        let state = self.state.lock().unwrap();
+        let mut keys: Vec<ApiKeySummary> = state.api_keys.values()
+            .filter(|key| user_id.is_none_or(|user_id| key.user_id == user_id))
+            .cloned()
+            .collect();
+        keys.sort_by_key(|key| std::cmp::Reverse(key.created_at));
+        Ok(keys)
+    }
+
+    async fn rotate_api_key(&self, key_id: &str, key_hash: &str, key_prefix: &str) -> Result<Option<(ApiKeySummary, Option<String>)>> {
+        let mut state = self.state.lock().unwrap();
+        let Some(key) = state.api_keys.get_mut(key_id).filter(|key| key.status == "active") else {
+            return Ok(None);
+        };
+        let old_key_hash = key.key_hash.replace(key_hash.to_string());
+        key.key_prefix = display_prefix(Some(key_prefix.to_string()));
+        Ok(Some((key.clone(), old_key_hash)))
+    }
+
+    async fn set_api_key_expiry(&self, key_id: &str, expires_at: Option<DateTime<Utc>>) -> Result<Option<ApiKeySummary>> {
+        let mut state = self.state.lock().unwrap();
+        Ok(state.api_keys.get_mut(key_id).map(|key| {
+            key.expires_at = expires_at;
+            key.clone()
+        }))
+    }
+
+    async fn set_api_key_tier(&self, key_id: &str, tier: Option<&str>) -> Result<Option<ApiKeySummary>> {
+        let mut state = self.state.lock().unwrap();
+        Ok(state.api_keys.get_mut(key_id).map(|key| {
+            key.tier = tier.map(str::to_string);
+            key.clone()
+        }))
+    }
+
+    async fn revoke_api_key(&self, key_id: &str) -> Result<Option<ApiKeySummary>> {
+        let mut state = self.state.lock().unwrap();
+        Ok(state.api_keys.get_mut(key_id).map(|key| {
+            key.status = "revoked".to_string();
+            key.clone()
+        }))
+    }
+
+    async fn insert_client_token(&self, token_hash: &str, token_prefix: &str, user_id: &str, expires_at: DateTime<Utc>) -> Result<()> {
+        let token = StoredClientToken {
+            token_hash: token_hash.to_string(),
+            token_prefix: token_prefix.to_string(),
+            user_id: user_id.to_string(),
+            expires_at,
+            revoked_at: None,
+            created_at: Utc::now(),
+        };
+        self.state.lock().unwrap().client_tokens.insert(Uuid::new_v4().to_string(), token);
+        Ok(())
+    }
+
+    async fn find_client_token(&self, token_hash: &str) -> Result<Option<ClientToken>> {
+        let state = self.state.lock().unwrap();
+        let now = Utc::now();
+        Ok(state.client_tokens.iter()
+            .find(|(_, token)| token.token_hash == token_hash && token.is_live(now))
+            .filter(|(_, token)| state.users.get(&token.user_id).is_some_and(|user| user.status == UserStatus::Active))
+            .map(|(id, token)| ClientToken {
+                id: id.clone(),
+                user_id: token.user_id.clone(),
+                expires_at: token.expires_at,
+            }))
+    }
+
+    async fn revoke_client_token(&self, token_id: &str) -> Result<Vec<Option<String>>> {
+        Ok(self.state.lock().unwrap().revoke_tokens(|id, _| id == token_id))
+    }
+
+    async fn revoke_user_client_tokens(&self, user_id: &str) -> Result<Vec<Option<String>>> {
+        Ok(self.state.lock().unwrap().revoke_tokens(|_, token| token.user_id == user_id))
+    }
+
+    async fn list_active_client_tokens(&self) -> Result<Vec<ClientTokenSummary>> {
+        let state = self.state.lock().unwrap();
+        let now = Utc::now();
+        let mut tokens: Vec<ClientTokenSummary> = state.client_tokens.iter()
+            .filter(|(_, token)| token.is_live(now))
+            .map(|(id, token)| ClientTokenSummary {
+                id: id.clone(),
+                user_id: token.user_id.clone(),
+                token_prefix: display_prefix(Some(token.token_prefix.clone())),
+                created_at: token.created_at,
+                expires_at: token.expires_at,
+            })
+            .collect();
+        tokens.sort_by_key(|token| std::cmp::Reverse(token.created_at));
+        Ok(tokens)
+    }
+
+    async fn find_user_by_email(&self, email: &str) -> Result<Option<User>> {
+        let state = self.state.lock().unwrap();
+        Ok(state.users.values().find(|user| user.email == email).cloned())
+    }
+
+    async fn list_users(&self) -> Result<Vec<User>> {
+        let state = self.state.lock().unwrap();
+        let mut users: Vec<User> = state.users.values().cloned().collect();
+        users.sort_by_key(|user| user.created_at);
+        Ok(users)
+    }
+
+    async fn create_user(&self, id: &str, email: &str, password_hash: &str) -> Result<Option<User>> {
+        let mut state = self.state.lock().unwrap();
+        if state.users.values().any(|user| user.email == email) {
+            return Ok(None);
+        }
+        let user = User {
+            id: id.to_string(),
+            email: email.to_string(),
+            password_hash: password_hash.to_string(),
+            status: UserStatus::Active,
+            failed_login_attempts: 0,
+            created_at: Utc::now(),
+        };
+        state.users.insert(user.id.clone(), user.clone());
+        Ok(Some(user))
+    }
+
+    async fn set_user_status(&self, user_id: &str, status: UserStatus) -> Result<bool> {
+        let mut state = self.state.lock().unwrap();
+        Ok(state.users.get_mut(user_id).map(|user| {
+            user.status = status;
+            user.failed_login_attempts = 0;
+        }).is_some())
+    }
+
+    async fn set_user_password(&self, user_id: &str, password_hash: &str) -> Result<bool> {
+        let mut state = self.state.lock().unwrap();
+        Ok(state.users.get_mut(user_id).map(|user| {
+            user.password_hash = password_hash.to_string();
+            user.failed_login_attempts = 0;
+            if user.status == UserStatus::Locked {
+                user.status = UserStatus::Active;
+            }
+        }).is_some())
+    }
+
+    async fn record_failed_login(&self, user_id: &str, max_attempts: i32) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(user) = state.users.get_mut(user_id) {
+            user.failed_login_attempts += 1;
+            if user.failed_login_attempts >= max_attempts {
+                user.status = UserStatus::Locked;
+            }
+        }
+        Ok(())
+    }
+
+    async fn reset_failed_logins(&self, user_id: &str) -> Result<()> {
+        if let Some(user) = self.state.lock().unwrap().users.get_mut(user_id) {
+            user.failed_login_attempts = 0;
+        }
+        Ok(())
+    }
+
+    async fn upsert_client_info(&self, user_id: &str, machine_id: &str, _name: &str, status: &str) -> Result<bool> {
+        let mut state = self.state.lock().unwrap();
+        let machine = state.machines.entry(machine_id.to_string()).or_insert_with(|| Machine {
+            user_id: user_id.to_string(),
+            status: status.to_string(),
+        });
+        if machine.user_id != user_id {
+            return Ok(false);
+        }
+        machine.status = status.to_string();
+        Ok(true)
+    }
+
+    async fn machine_owner(&self, machine_id: &str) -> Result<Option<String>> {
+        Ok(self.state.lock().unwrap().machines.get(machine_id).map(|machine| machine.user_id.clone()))
+    }
+
+    async fn set_machine_owner(&self, machine_id: &str, user_id: &str) -> Result<bool> {
+        let mut state = self.state.lock().unwrap();
+        let Some(machine) = state.machines.get_mut(machine_id) else {
+            return Ok(false);
+        };
+        machine.user_id = user_id.to_string();
+        Ok(true)
+    }
+
+    async fn set_client_status(&self, machine_id: &str, status: &str) -> Result<()> {
+        if let Some(machine) = self.state.lock().unwrap().machines.get_mut(machine_id) {
+            machine.status = status.to_string();
+        }
+        Ok(())
+    }
+
+    async fn owned_machine_ids(&self, user_id: &str) -> Result<HashSet<String>> {
+        let state = self.state.lock().unwrap();
+        Ok(state.machines.iter()
+            .filter(|(_, machine)| machine.user_id == user_id && machine.status == "online")
+            .map(|(machine_id, _)| machine_id.clone())
+            .collect())
+    }
+
+    async fn open_client_session(&self, session: NewClientSession<'_>) -> Result<String> {
+        let mut state = self.state.lock().unwrap();
+        for open in state.sessions.iter_mut().filter(|s| s.machine_id == session.machine_id && s.disconnected_at.is_none()) {
+            open.disconnected_at = Some(open.connected_at);
+            open.disconnect_reason = Some(ABANDONED_SESSION.to_string());
+        }
+        let id = Uuid::new_v4().to_string();
+        state.sessions.push(ClientSession {
+            id: id.clone(),
+            machine_id: session.machine_id.to_string(),
+            user_id: session.user_id.to_string(),
+            remote_addr: Some(session.remote_addr.to_string()),
+            client_version: session.client_version.map(str::to_string),
+            connected_at: Utc::now(),
+            disconnected_at: None,
+            disconnect_reason: None,
+        });
+        Ok(id)
+    }
+
+    async fn close_client_session(&self, session_id: &str, reason: &str) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(session) = state.sessions.iter_mut().find(|s| s.id == session_id && s.disconnected_at.is_none()) {
+            session.disconnected_at = Some(Utc::now());
+            session.disconnect_reason = Some(reason.to_string());
+        }
+        Ok(())
+    }
+
+    async fn client_sessions(&self, machine_id: &str, since: DateTime<Utc>) -> Result<Vec<ClientSession>> {
+        let state = self.state.lock().unwrap();
+        // Sessions are appended in connection order
+        Ok(state.sessions.iter()
+            .rev()
+            .filter(|s| s.machine_id == machine_id && s.disconnected_at.is_none_or(|t| t > since))
+            .cloned()
+            .collect())
+    }
+
+    async fn ban_client(&self, machine_id: &str, until: DateTime<Utc>, _reason: Option<&str>) -> Result<()> {
+        self.state.lock().unwrap().client_bans.insert(machine_id.to_string(), until);
+        Ok(())
+    }
+
+    async fn client_ban(&self, machine_id: &str) -> Result<Option<DateTime<Utc>>> {
+        let state = self.state.lock().unwrap();
+        Ok(state.client_bans.get(machine_id).copied().filter(|until| *until > Utc::now()))
+    }
+
+    async fn lift_client_ban(&self, machine_id: &str) -> Result<bool> {
+        let removed = self.state.lock().unwrap().client_bans.remove(machine_id);
+        Ok(removed.is_some_and(|until| until > Utc::now()))
+    }
+
+    async fn record_metric_sample(&self, machine_id: &str, sample: &MetricSample) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.metric_samples.entry(machine_id.to_string()).or_default().push_back(sample.clone());
+        Ok(())
+    }
+
+    async fn metric_samples(&self, machine_id: &str, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<MetricSample>> {
+        let state = self.state.lock().unwrap();
+        Ok(state.metric_samples.get(machine_id)
+            .map(|samples| samples.iter()
+                .filter(|s| s.recorded_at >= from && s.recorded_at < to)
+                .cloned()
+                .collect())
+            .unwrap_or_default())
+    }
+
+    async fn prune_metric_samples(&self, before: DateTime<Utc>) -> Result<u64> {
+        let mut state = self.state.lock().unwrap();
+        let mut removed = 0;
+        // Samples are appended in time order, so old ones are at the front
+        for samples in state.metric_samples.values_mut() {
+            while samples.front().is_some_and(|s| s.recorded_at < before) {
+                samples.pop_front();
+                removed += 1;
+            }
+        }
+        state.metric_samples.retain(|_, samples| !samples.is_empty());
+        Ok(removed)
+    }
+
+    async fn add_traffic(&self, kind: TrafficKind, id: &str, upload_bytes: u64, download_bytes: u64) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let totals = state.traffic.entry((kind, id.to_string())).or_default();
+        totals.0 += upload_bytes;
+        totals.1 += download_bytes;
+        Ok(())
+    }
+
+    async fn traffic_totals(&self, kind: TrafficKind) -> Result<Vec<TrafficTotal>> {
+        let state = self.state.lock().unwrap();
+        let mut totals: Vec<TrafficTotal> = state.traffic.iter()
+            .filter(|((total_kind, _), _)| *total_kind == kind)
+            .map(|((_, id), (upload_bytes, download_bytes))| TrafficTotal { id: id.clone(), upload_bytes: *upload_bytes, download_bytes: *download_bytes })
+            .collect();
+        totals.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(totals)
+    }
+
+    async fn record_audit_entry(&self, principal: &str, role: &str, method: &str, path: &str, status: u16) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.audit_log.len() == AUDIT_LOG_CAPACITY {
+            state.audit_log.pop_front();
+        }
+        state.audit_log.push_back(AuditEntry {
+            principal: principal.to_string(),
+            role: role.to_string(),
+            method: method.to_string(),
+            path: path.to_string(),
+            status,
+        });
+        Ok(())
+    }
+}
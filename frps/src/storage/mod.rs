@@ -0,0 +1,421 @@
+//! Persistence for users, API keys, frpc session tokens, machines and the audit log.
+//!
+//! The backend is chosen from the scheme of `--database-url`: `postgres://`,
+//! `sqlite://` or `memory://`. Secrets never reach this layer; callers pass
+//! keyed hashes and password hashes only.
+
+mod memory;
+mod postgres;
+mod sqlite;
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+pub use memory::MemoryStorage;
+pub use postgres::PostgresStorage;
+pub use sqlite::SqliteStorage;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UserStatus {
+    Active,
+    Disabled,
+    Locked,
+}
+
+impl UserStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UserStatus::Active => "active",
+            UserStatus::Disabled => "disabled",
+            UserStatus::Locked => "locked",
+        }
+    }
+
+    /// Unknown values are treated as disabled so that a bad row never grants access.
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "active" => UserStatus::Active,
+            "locked" => UserStatus::Locked,
+            _ => UserStatus::Disabled,
+        }
+    }
+}
+
+/// A row of the `users` table.
+#[derive(Serialize, Clone)]
+pub struct User {
+    pub id: String,
+    pub email: String,
+    #[serde(skip)]
+    pub password_hash: String,
+    pub status: UserStatus,
+    pub failed_login_attempts: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+/// The active API key matching a presented secret.
+#[derive(Clone, Debug)]
+pub struct ApiKeyOwner {
+    pub key_id: String,
+    pub user_id: String,
+    pub tier: Option<String>,
+}
+
+/// A valid session token issued to frpc by `Login`.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ClientToken {
+    pub id: String,
+    pub user_id: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct ClientTokenSummary {
+    pub id: String,
+    pub user_id: String,
+    pub token_prefix: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// An API key as shown by the management API. The secret itself is only
+/// returned once, when the key is created or rotated.
+#[derive(Serialize, Clone)]
+pub struct ApiKeySummary {
+    pub id: String,
+    pub user_id: String,
+    pub name: Option<String>,
+    pub key_prefix: String,
+    pub status: String,
+    /// Bandwidth tier, see `--tier-bandwidth`.
+    pub tier: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    /// Used to drop cached validation results; `None` for legacy plaintext rows.
+    #[serde(skip)]
+    pub key_hash: Option<String>,
+}
+
+/// A control connection of a machine, from registration to disconnect.
+#[derive(Serialize, Clone)]
+pub struct ClientSession {
+    pub id: String,
+    pub machine_id: String,
+    pub user_id: String,
+    pub remote_addr: Option<String>,
+    pub client_version: Option<String>,
+    pub connected_at: DateTime<Utc>,
+    /// `None` while the session is still open.
+    pub disconnected_at: Option<DateTime<Utc>>,
+    pub disconnect_reason: Option<String>,
+}
+
+pub struct NewClientSession<'a> {
+    pub machine_id: &'a str,
+    pub user_id: &'a str,
+    pub remote_addr: &'a str,
+    pub client_version: Option<&'a str>,
+}
+
+/// System metrics of a machine, averaged over one sampling interval.
+#[derive(Serialize, Clone, Debug)]
+pub struct MetricSample {
+    pub recorded_at: DateTime<Utc>,
+    pub cpu_usage: f32,
+    pub memory_usage: f32,
+    pub disk_usage: f32,
+}
+
+/// Whose proxied traffic a byte total counts.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum TrafficKind {
+    Client,
+    ApiKey,
+}
+
+impl TrafficKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TrafficKind::Client => "client",
+            TrafficKind::ApiKey => "api_key",
+        }
+    }
+}
+
+/// Cumulative proxied bytes of a client or API key, from the user's point of view.
+#[derive(Serialize, Clone, Debug)]
+pub struct TrafficTotal {
+    pub id: String,
+    pub upload_bytes: u64,
+    pub download_bytes: u64,
+}
+
+/// Reason recorded for sessions left open by an frps instance that exited without closing them.
+pub const ABANDONED_SESSION: &str = "abandoned";
+
+pub struct NewApiKey<'a> {
+    pub key_hash: &'a str,
+    pub key_prefix: &'a str,
+    pub user_id: &'a str,
+    pub name: Option<&'a str>,
+    pub tier: Option<&'a str>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Hash function applied to legacy plaintext secrets.
+pub type SecretHasher<'a> = &'a (dyn Fn(&str) -> String + Send + Sync);
+
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Applies the schema migrations embedded in the binary that have not run yet.
+    /// Backends without a schema have nothing to do.
+    async fn migrate(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Returns the owner of the active, unexpired API key with `key_hash`.
+    async fn api_key_owner(&self, key_hash: &str) -> Result<Option<ApiKeyOwner>>;
+    async fn create_api_key(&self, key: NewApiKey<'_>) -> Result<ApiKeySummary>;
+    async fn list_api_keys(&self, user_id: Option<&str>) -> Result<Vec<ApiKeySummary>>;
+    /// Replaces the hash of an active key. Returns the updated key and its previous hash,
+    /// or `None` if no active key has `key_id`.
+    async fn rotate_api_key(&self, key_id: &str, key_hash: &str, key_prefix: &str) -> Result<Option<(ApiKeySummary, Option<String>)>>;
+    async fn set_api_key_expiry(&self, key_id: &str, expires_at: Option<DateTime<Utc>>) -> Result<Option<ApiKeySummary>>;
+    async fn set_api_key_tier(&self, key_id: &str, tier: Option<&str>) -> Result<Option<ApiKeySummary>>;
+    async fn revoke_api_key(&self, key_id: &str) -> Result<Option<ApiKeySummary>>;
+
+    /// Hashes API keys and session tokens stored in plaintext by older versions
+    /// and clears the plaintext. Returns the number of rows converted.
+    async fn hash_legacy_secrets(&self, _hash: SecretHasher<'_>) -> Result<u64> {
+        Ok(0)
+    }
+
+    async fn insert_client_token(&self, token_hash: &str, token_prefix: &str, user_id: &str, expires_at: DateTime<Utc>) -> Result<()>;
+    /// Returns the live token with `token_hash`, provided its user is active.
+    async fn find_client_token(&self, token_hash: &str) -> Result<Option<ClientToken>>;
+    /// Revokes a live token. Returns one entry per revoked token: its hash,
+    /// or `None` for a legacy plaintext row.
+    async fn revoke_client_token(&self, token_id: &str) -> Result<Vec<Option<String>>>;
+    /// Revokes every live token of `user_id`, returning entries as `revoke_client_token` does.
+    async fn revoke_user_client_tokens(&self, user_id: &str) -> Result<Vec<Option<String>>>;
+    async fn list_active_client_tokens(&self) -> Result<Vec<ClientTokenSummary>>;
+
+    async fn find_user_by_email(&self, email: &str) -> Result<Option<User>>;
+    async fn list_users(&self) -> Result<Vec<User>>;
+    /// Returns `None` if the email is already registered.
+    async fn create_user(&self, id: &str, email: &str, password_hash: &str) -> Result<Option<User>>;
+    /// Also clears failed login attempts. Returns `false` if no user has `user_id`.
+    async fn set_user_status(&self, user_id: &str, status: UserStatus) -> Result<bool>;
+    /// Sets a new password hash and unlocks the account. Disabled accounts stay disabled.
+    async fn set_user_password(&self, user_id: &str, password_hash: &str) -> Result<bool>;
+    /// Counts a failed password attempt and locks the account once `max_attempts` is reached.
+    async fn record_failed_login(&self, user_id: &str, max_attempts: i32) -> Result<()>;
+    async fn reset_failed_logins(&self, user_id: &str) -> Result<()>;
+
+    /// Stores a machine for `user_id`. Returns `false`, leaving the machine as it is,
+    /// when it is registered to another user.
+    async fn upsert_client_info(&self, user_id: &str, machine_id: &str, name: &str, status: &str) -> Result<bool>;
+    /// Returns the user a machine is registered to, if it was ever registered.
+    async fn machine_owner(&self, machine_id: &str) -> Result<Option<String>>;
+    /// Moves a machine to `user_id`. Returns `false` if the machine is unknown.
+    async fn set_machine_owner(&self, machine_id: &str, user_id: &str) -> Result<bool>;
+    async fn set_client_status(&self, machine_id: &str, status: &str) -> Result<()>;
+    /// Returns the ids of the online machines registered to `user_id`.
+    async fn owned_machine_ids(&self, user_id: &str) -> Result<HashSet<String>>;
+
+    /// Records a new session and returns its id. Sessions of the same machine that are
+    /// still open are closed as abandoned; since their real end is unknown they are
+    /// closed at the time they started, so they never count as uptime.
+    async fn open_client_session(&self, session: NewClientSession<'_>) -> Result<String>;
+    async fn close_client_session(&self, session_id: &str, reason: &str) -> Result<()>;
+    /// Returns the sessions of `machine_id` that were open at any time since `since`, newest first.
+    async fn client_sessions(&self, machine_id: &str, since: DateTime<Utc>) -> Result<Vec<ClientSession>>;
+
+    /// Bans `machine_id` from registering until `until`, replacing any earlier ban.
+    async fn ban_client(&self, machine_id: &str, until: DateTime<Utc>, reason: Option<&str>) -> Result<()>;
+    /// Returns the end of the ban on `machine_id`, if one is in effect.
+    async fn client_ban(&self, machine_id: &str) -> Result<Option<DateTime<Utc>>>;
+    /// Returns `false` if `machine_id` was not banned.
+    async fn lift_client_ban(&self, machine_id: &str) -> Result<bool>;
+
+    async fn record_metric_sample(&self, machine_id: &str, sample: &MetricSample) -> Result<()>;
+    /// Returns the samples of `machine_id` recorded in `[from, to)`, oldest first.
+    async fn metric_samples(&self, machine_id: &str, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<MetricSample>>;
+    /// Deletes samples recorded before `before`, returning how many were removed.
+    async fn prune_metric_samples(&self, before: DateTime<Utc>) -> Result<u64>;
+
+    /// Adds proxied bytes to the totals of a client or API key.
+    async fn add_traffic(&self, kind: TrafficKind, id: &str, upload_bytes: u64, download_bytes: u64) -> Result<()>;
+    async fn traffic_totals(&self, kind: TrafficKind) -> Result<Vec<TrafficTotal>>;
+
+    async fn record_audit_entry(&self, principal: &str, role: &str, method: &str, path: &str, status: u16) -> Result<()>;
+}
+
+/// Whether `url` names the in-memory backend, which keeps nothing across restarts.
+pub fn is_memory_url(url: &str) -> bool {
+    url == "memory" || url.starts_with("memory:")
+}
+
+/// Opens the backend named by the scheme of `url`.
+pub async fn connect(url: &str) -> Result<Arc<dyn Storage>> {
+    if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+        Ok(Arc::new(PostgresStorage::connect(url).await?))
+    } else if url.starts_with("sqlite:") {
+        Ok(Arc::new(SqliteStorage::connect(url).await?))
+    } else if is_memory_url(url) {
+        Ok(Arc::new(MemoryStorage::default()))
+    } else {
+        bail!("Unsupported database URL {}; expected postgres://, sqlite:// or memory://", url)
+    }
+}
+
+/// The public part of a secret that is kept for display, e.g. `sk-6g4KH`.
+pub fn secret_prefix(secret: &str) -> String {
+    secret.chars().take(8).collect()
+}
+
+fn display_prefix(prefix: Option<String>) -> String {
+    format!("{}...", prefix.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use uuid::Uuid;
+
+    /// The backends to test: memory, in-memory SQLite, and Postgres when
+    /// `FRPS_TEST_POSTGRES_URL` points at a scratch database.
+    async fn backends() -> Vec<Arc<dyn Storage>> {
+        let sqlite = connect("sqlite::memory:").await.unwrap();
+        sqlite.migrate().await.unwrap();
+        let mut storages = vec![connect("memory://").await.unwrap(), sqlite];
+        if let Ok(url) = std::env::var("FRPS_TEST_POSTGRES_URL") {
+            let postgres = connect(&url).await.unwrap();
+            postgres.migrate().await.unwrap();
+            storages.push(postgres);
+        }
+        storages
+    }
+
+    async fn new_user(storage: &Arc<dyn Storage>) -> User {
+        let id = Uuid::new_v4().to_string();
+        storage.create_user(&id, &format!("{}@example.com", id), "hash").await.unwrap().unwrap()
+    }
+
+    #[tokio::test]
+    async fn other_user_cannot_take_a_machine() {
+        let storages = backends().await;
+        for storage in storages {
+            // Unique ids so a shared Postgres database can be reused between runs
+            let machine_id = format!("machine-{}", Uuid::new_v4());
+            let owner = format!("owner-{}", Uuid::new_v4());
+            let other = format!("other-{}", Uuid::new_v4());
+            assert!(storage.upsert_client_info(&owner, &machine_id, "gpu-box", "online").await.unwrap());
+            assert!(storage.upsert_client_info(&owner, &machine_id, "gpu-box", "online").await.unwrap());
+
+            assert!(!storage.upsert_client_info(&other, &machine_id, "gpu-box", "online").await.unwrap());
+            assert_eq!(storage.machine_owner(&machine_id).await.unwrap(), Some(owner.clone()));
+            assert_eq!(storage.owned_machine_ids(&owner).await.unwrap(), HashSet::from([machine_id.clone()]));
+            assert!(storage.owned_machine_ids(&other).await.unwrap().is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn admin_moves_a_machine_to_another_user() {
+        let storages = backends().await;
+        for storage in storages {
+            let machine_id = format!("machine-{}", Uuid::new_v4());
+            let owner = format!("owner-{}", Uuid::new_v4());
+            let new_owner = format!("new-{}", Uuid::new_v4());
+            assert!(!storage.set_machine_owner(&machine_id, &new_owner).await.unwrap());
+            storage.upsert_client_info(&owner, &machine_id, "gpu-box", "online").await.unwrap();
+
+            assert!(storage.set_machine_owner(&machine_id, &new_owner).await.unwrap());
+            assert_eq!(storage.machine_owner(&machine_id).await.unwrap(), Some(new_owner.clone()));
+            assert!(!storage.upsert_client_info(&owner, &machine_id, "gpu-box", "online").await.unwrap());
+            assert_eq!(storage.owned_machine_ids(&new_owner).await.unwrap(), HashSet::from([machine_id]));
+        }
+    }
+
+    #[tokio::test]
+    async fn api_keys_validate_until_revoked_or_expired() {
+        for storage in backends().await {
+            let user = new_user(&storage).await;
+            let key_hash = Uuid::new_v4().to_string();
+            let key = storage.create_api_key(NewApiKey {
+                key_hash: &key_hash,
+                key_prefix: "sk-test",
+                user_id: &user.id,
+                name: Some("laptop"),
+                tier: Some("pro"),
+                expires_at: None,
+            }).await.unwrap();
+
+            let owner = storage.api_key_owner(&key_hash).await.unwrap().unwrap();
+            assert_eq!((owner.key_id.as_str(), owner.user_id.as_str()), (key.id.as_str(), user.id.as_str()));
+            assert_eq!(owner.tier.as_deref(), Some("pro"));
+            assert!(storage.api_key_owner("no-such-hash").await.unwrap().is_none());
+
+            assert!(storage.revoke_api_key(&key.id).await.unwrap().is_some());
+            assert!(storage.api_key_owner(&key_hash).await.unwrap().is_none());
+
+            let expired_hash = Uuid::new_v4().to_string();
+            storage.create_api_key(NewApiKey {
+                key_hash: &expired_hash,
+                key_prefix: "sk-test",
+                user_id: &user.id,
+                name: None,
+                tier: None,
+                expires_at: Some(Utc::now() - Duration::minutes(1)),
+            }).await.unwrap();
+            assert!(storage.api_key_owner(&expired_hash).await.unwrap().is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn client_tokens_stop_working_after_expiry_or_revocation() {
+        for storage in backends().await {
+            let user = new_user(&storage).await;
+            let live_hash = Uuid::new_v4().to_string();
+            let expired_hash = Uuid::new_v4().to_string();
+            storage.insert_client_token(&live_hash, "ct-live", &user.id, Utc::now() + Duration::days(1)).await.unwrap();
+            storage.insert_client_token(&expired_hash, "ct-old", &user.id, Utc::now() - Duration::minutes(1)).await.unwrap();
+
+            assert!(storage.find_client_token(&expired_hash).await.unwrap().is_none());
+            let token = storage.find_client_token(&live_hash).await.unwrap().unwrap();
+            assert_eq!(token.user_id, user.id);
+
+            storage.revoke_client_token(&token.id).await.unwrap();
+            assert!(storage.find_client_token(&live_hash).await.unwrap().is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_logins_lock_the_account_at_the_limit() {
+        for storage in backends().await {
+            let user = new_user(&storage).await;
+            storage.record_failed_login(&user.id, 3).await.unwrap();
+            storage.record_failed_login(&user.id, 3).await.unwrap();
+            let user = storage.find_user_by_email(&user.email).await.unwrap().unwrap();
+            assert_eq!((user.status, user.failed_login_attempts), (UserStatus::Active, 2));
+
+            storage.reset_failed_logins(&user.id).await.unwrap();
+            for _ in 0..3 {
+                storage.record_failed_login(&user.id, 3).await.unwrap();
+            }
+            let user = storage.find_user_by_email(&user.email).await.unwrap().unwrap();
+            assert_eq!((user.status, user.failed_login_attempts), (UserStatus::Locked, 3));
+
+            assert!(storage.set_user_password(&user.id, "new-hash").await.unwrap());
+            let user = storage.find_user_by_email(&user.email).await.unwrap().unwrap();
+            assert_eq!((user.status, user.failed_login_attempts), (UserStatus::Active, 0));
+        }
+    }
+}
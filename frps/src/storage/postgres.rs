@@ -0,0 +1,628 @@
+use super::{display_prefix, secret_prefix, ApiKeyOwner, ApiKeySummary, ClientSession, ClientToken, ClientTokenSummary, MetricSample, NewApiKey, NewClientSession, SecretHasher, Storage, TrafficKind, TrafficTotal, User, UserStatus, ABANDONED_SESSION};
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::postgres::{PgPoolOptions, PgRow};
+use sqlx::{Pool, Postgres, Row};
+use std::collections::HashSet;
+use uuid::Uuid;
+
+pub struct PostgresStorage {
+    pool: Pool<Postgres>,
+}
+
+impl PostgresStorage {
+    pub async fn connect(url: &str) -> Result<Self> {
+        let pool = PgPoolOptions::new()
+            .max_connections(10)
+            .connect(url)
+            .await?;
+        Ok(Self { pool })
+    }
+}
+
+const API_KEY_COLUMNS: &str = "id, \"keyHash\", \"keyPrefix\", \"userId\", name, status, tier, \"expiresAt\", \"createdAt\"";
+
+fn api_key_from_row(row: &PgRow) -> ApiKeySummary {
+    let expires_at: Option<chrono::NaiveDateTime> = row.get("expiresAt");
+    let created_at: chrono::NaiveDateTime = row.get("createdAt");
+    ApiKeySummary {
+        id: row.get("id"),
+        user_id: row.get("userId"),
+        name: row.get("name"),
+        key_prefix: display_prefix(row.get("keyPrefix")),
+        status: row.get("status"),
+        tier: row.get("tier"),
+        expires_at: expires_at.map(|t| t.and_utc()),
+        created_at: created_at.and_utc(),
+        key_hash: row.get("keyHash"),
+    }
+}
+
+const USER_COLUMNS: &str = "id, email, \"passwordHash\", status, \"failedLoginAttempts\", \"createdAt\"";
+
+fn user_from_row(row: &PgRow) -> User {
+    let created_at: chrono::NaiveDateTime = row.get("createdAt");
+    User {
+        id: row.get("id"),
+        email: row.get("email"),
+        password_hash: row.get("passwordHash"),
+        status: UserStatus::parse(row.get("status")),
+        failed_login_attempts: row.get("failedLoginAttempts"),
+        created_at: created_at.and_utc(),
+    }
+}
+
+fn is_unique_violation(e: &sqlx::Error) -> bool {
+    e.as_database_error().is_some_and(|db_err| db_err.is_unique_violation())
+}
+
+#[async_trait]
+impl Storage for PostgresStorage {
+    async fn migrate(&self) -> Result<()> {
+        sqlx::migrate!("./migrations/postgres").run(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn api_key_owner(&self, key_hash: &str) -> Result<Option<ApiKeyOwner>> {
+        let row = sqlx::query(
+            "SELECT id, \"userId\", tier FROM \"public\".\"api_keys\" WHERE \"keyHash\" = $1 AND status = 'active' AND (\"expiresAt\" IS NULL OR \"expiresAt\" > NOW())"
+        )
+        .bind(key_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|r| ApiKeyOwner { key_id: r.get("id"), user_id: r.get("userId"), tier: r.get("tier") }))
+    }
+
+    async fn create_api_key(&self, key: NewApiKey<'_>) -> Result<ApiKeySummary> {
+        let row = sqlx::query(&format!(
+            "INSERT INTO \"public\".\"api_keys\" (id, \"keyHash\", \"keyPrefix\", \"userId\", name, status, tier, \"expiresAt\", \"createdAt\", \"updatedAt\") \
+             VALUES ($1, $2, $3, $4, $5, 'active', $6, $7, NOW(), NOW()) RETURNING {}",
+            API_KEY_COLUMNS
+        ))
+        .bind(Uuid::new_v4().to_string())
+        .bind(key.key_hash)
+        .bind(key.key_prefix)
+        .bind(key.user_id)
+        .bind(key.name)
+        .bind(key.tier)
+        .bind(key.expires_at.map(|t| t.naive_utc()))
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(api_key_from_row(&row))
+    }
+
+    async fn list_api_keys(&self, user_id: Option<&str>) -> Result<Vec<ApiKeySummary>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM \"public\".\"api_keys\" WHERE $1::VARCHAR IS NULL OR \"userId\" = $1 ORDER BY \"createdAt\" DESC",
+            API_KEY_COLUMNS
+        ))
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(api_key_from_row).collect())
+    }
+
+    async fn rotate_api_key(&self, key_id: &str, key_hash: &str, key_prefix: &str) -> Result<Option<(ApiKeySummary, Option<String>)>> {
+        let mut tx = self.pool.begin().await?;
+
+        let old_key_hash: Option<Option<String>> = sqlx::query_scalar(
+            "SELECT \"keyHash\" FROM \"public\".\"api_keys\" WHERE id = $1 AND status = 'active' FOR UPDATE"
+        )
+        .bind(key_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(old_key_hash) = old_key_hash else {
+            return Ok(None);
+        };
+
+        // Legacy rows still holding a plaintext key lose it here as well
+        let row = sqlx::query(&format!(
+            "UPDATE \"public\".\"api_keys\" SET key = NULL, \"keyHash\" = $2, \"keyPrefix\" = $3, \"updatedAt\" = NOW() WHERE id = $1 RETURNING {}",
+            API_KEY_COLUMNS
+        ))
+        .bind(key_id)
+        .bind(key_hash)
+        .bind(key_prefix)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(Some((api_key_from_row(&row), old_key_hash)))
+    }
+
+    async fn set_api_key_expiry(&self, key_id: &str, expires_at: Option<DateTime<Utc>>) -> Result<Option<ApiKeySummary>> {
+        let row = sqlx::query(&format!(
+            "UPDATE \"public\".\"api_keys\" SET \"expiresAt\" = $2, \"updatedAt\" = NOW() WHERE id = $1 RETURNING {}",
+            API_KEY_COLUMNS
+        ))
+        .bind(key_id)
+        .bind(expires_at.map(|t| t.naive_utc()))
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.as_ref().map(api_key_from_row))
+    }
+
+    async fn set_api_key_tier(&self, key_id: &str, tier: Option<&str>) -> Result<Option<ApiKeySummary>> {
+        let row = sqlx::query(&format!(
+            "UPDATE \"public\".\"api_keys\" SET tier = $2, \"updatedAt\" = NOW() WHERE id = $1 RETURNING {}",
+            API_KEY_COLUMNS
+        ))
+        .bind(key_id)
+        .bind(tier)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.as_ref().map(api_key_from_row))
+    }
+
+    async fn revoke_api_key(&self, key_id: &str) -> Result<Option<ApiKeySummary>> {
+        let row = sqlx::query(&format!(
+            "UPDATE \"public\".\"api_keys\" SET status = 'revoked', \"updatedAt\" = NOW() WHERE id = $1 RETURNING {}",
+            API_KEY_COLUMNS
+        ))
+        .bind(key_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.as_ref().map(api_key_from_row))
+    }
+
+    async fn hash_legacy_secrets(&self, hash: SecretHasher<'_>) -> Result<u64> {
+        let mut tx = self.pool.begin().await?;
+        let mut converted = 0;
+
+        let api_keys = sqlx::query("SELECT id, key FROM \"public\".\"api_keys\" WHERE key IS NOT NULL AND \"keyHash\" IS NULL FOR UPDATE")
+            .fetch_all(&mut *tx)
+            .await?;
+        for row in &api_keys {
+            let key: String = row.get("key");
+            sqlx::query("UPDATE \"public\".\"api_keys\" SET key = NULL, \"keyHash\" = $2, \"keyPrefix\" = $3 WHERE id = $1")
+                .bind(row.get::<String, _>("id"))
+                .bind(hash(&key))
+                .bind(secret_prefix(&key))
+                .execute(&mut *tx)
+                .await?;
+            converted += 1;
+        }
+
+        let client_tokens = sqlx::query("SELECT id, token FROM \"public\".\"client_tokens\" WHERE token IS NOT NULL AND \"tokenHash\" IS NULL FOR UPDATE")
+            .fetch_all(&mut *tx)
+            .await?;
+        for row in &client_tokens {
+            let token: String = row.get("token");
+            sqlx::query("UPDATE \"public\".\"client_tokens\" SET token = NULL, \"tokenHash\" = $2, \"tokenPrefix\" = $3 WHERE id = $1")
+                .bind(row.get::<String, _>("id"))
+                .bind(hash(&token))
+                .bind(secret_prefix(&token))
+                .execute(&mut *tx)
+                .await?;
+            converted += 1;
+        }
+
+        tx.commit().await?;
+        Ok(converted)
+    }
+
+    async fn insert_client_token(&self, token_hash: &str, token_prefix: &str, user_id: &str, expires_at: DateTime<Utc>) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO \"public\".\"client_tokens\" (id, \"tokenHash\", \"tokenPrefix\", \"userId\", \"expiresAt\", \"createdAt\") VALUES ($1, $2, $3, $4, $5, NOW())"
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(token_hash)
+        .bind(token_prefix)
+        .bind(user_id)
+        .bind(expires_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn find_client_token(&self, token_hash: &str) -> Result<Option<ClientToken>> {
+        let row = sqlx::query(
+            "SELECT t.id, t.\"userId\", t.\"expiresAt\" FROM \"public\".\"client_tokens\" t \
+             JOIN \"public\".\"users\" u ON u.id = t.\"userId\" \
+             WHERE t.\"tokenHash\" = $1 AND t.\"revokedAt\" IS NULL AND t.\"expiresAt\" > NOW() AND u.status = 'active'"
+        )
+        .bind(token_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|r| ClientToken {
+            id: r.get("id"),
+            user_id: r.get("userId"),
+            expires_at: r.get("expiresAt"),
+        }))
+    }
+
+    async fn revoke_client_token(&self, token_id: &str) -> Result<Vec<Option<String>>> {
+        let rows = sqlx::query(
+            "UPDATE \"public\".\"client_tokens\" SET \"revokedAt\" = NOW() WHERE id = $1 AND \"revokedAt\" IS NULL RETURNING \"tokenHash\""
+        )
+        .bind(token_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(|r| r.get("tokenHash")).collect())
+    }
+
+    async fn revoke_user_client_tokens(&self, user_id: &str) -> Result<Vec<Option<String>>> {
+        let rows = sqlx::query(
+            "UPDATE \"public\".\"client_tokens\" SET \"revokedAt\" = NOW() WHERE \"userId\" = $1 AND \"revokedAt\" IS NULL RETURNING \"tokenHash\""
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(|r| r.get("tokenHash")).collect())
+    }
+
+    async fn list_active_client_tokens(&self) -> Result<Vec<ClientTokenSummary>> {
+        let rows = sqlx::query(
+            "SELECT id, \"tokenPrefix\", \"userId\", \"createdAt\", \"expiresAt\" FROM \"public\".\"client_tokens\" \
+             WHERE \"revokedAt\" IS NULL AND \"expiresAt\" > NOW() ORDER BY \"createdAt\" DESC"
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(|r| ClientTokenSummary {
+            id: r.get("id"),
+            user_id: r.get("userId"),
+            token_prefix: display_prefix(r.get("tokenPrefix")),
+            created_at: r.get("createdAt"),
+            expires_at: r.get("expiresAt"),
+        }).collect())
+    }
+
+    async fn find_user_by_email(&self, email: &str) -> Result<Option<User>> {
+        let row = sqlx::query(&format!("SELECT {} FROM \"public\".\"users\" WHERE email = $1", USER_COLUMNS))
+            .bind(email)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.as_ref().map(user_from_row))
+    }
+
+    async fn list_users(&self) -> Result<Vec<User>> {
+        let rows = sqlx::query(&format!("SELECT {} FROM \"public\".\"users\" ORDER BY \"createdAt\"", USER_COLUMNS))
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.iter().map(user_from_row).collect())
+    }
+
+    async fn create_user(&self, id: &str, email: &str, password_hash: &str) -> Result<Option<User>> {
+        let result = sqlx::query(&format!(
+            "INSERT INTO \"public\".\"users\" (id, email, \"passwordHash\", status, \"failedLoginAttempts\", \"createdAt\", \"updatedAt\") \
+             VALUES ($1, $2, $3, 'active', 0, NOW(), NOW()) RETURNING {}",
+            USER_COLUMNS
+        ))
+        .bind(id)
+        .bind(email)
+        .bind(password_hash)
+        .fetch_one(&self.pool)
+        .await;
+
+        match result {
+            Ok(row) => Ok(Some(user_from_row(&row))),
+            Err(e) if is_unique_violation(&e) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn set_user_status(&self, user_id: &str, status: UserStatus) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE \"public\".\"users\" SET status = $2, \"failedLoginAttempts\" = 0, \"updatedAt\" = NOW() WHERE id = $1"
+        )
+        .bind(user_id)
+        .bind(status.as_str())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_user_password(&self, user_id: &str, password_hash: &str) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE \"public\".\"users\" SET \"passwordHash\" = $2, \"failedLoginAttempts\" = 0, \
+             status = CASE WHEN status = 'locked' THEN 'active' ELSE status END, \"updatedAt\" = NOW() WHERE id = $1"
+        )
+        .bind(user_id)
+        .bind(password_hash)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn record_failed_login(&self, user_id: &str, max_attempts: i32) -> Result<()> {
+        sqlx::query(
+            "UPDATE \"public\".\"users\" SET \"failedLoginAttempts\" = \"failedLoginAttempts\" + 1, \
+             status = CASE WHEN \"failedLoginAttempts\" + 1 >= $2 THEN 'locked' ELSE status END, \"updatedAt\" = NOW() WHERE id = $1"
+        )
+        .bind(user_id)
+        .bind(max_attempts)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn reset_failed_logins(&self, user_id: &str) -> Result<()> {
+        sqlx::query("UPDATE \"public\".\"users\" SET \"failedLoginAttempts\" = 0 WHERE id = $1")
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn upsert_client_info(&self, user_id: &str, machine_id: &str, name: &str, status: &str) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO "public"."gpu_assets" ("userId", "machineId", "name", "status", "createdAt", "updatedAt")
+            VALUES ($1, $2, $3, $4::gpu_asset_status, NOW(), NOW())
+            ON CONFLICT ("machineId")
+            DO UPDATE SET
+                "name" = EXCLUDED."name",
+                "status" = EXCLUDED."status",
+                "updatedAt" = NOW()
+            WHERE "gpu_assets"."userId" = EXCLUDED."userId";
+            "#
+        )
+        .bind(user_id)
+        .bind(machine_id)
+        .bind(name)
+        .bind(status)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn machine_owner(&self, machine_id: &str) -> Result<Option<String>> {
+        let row = sqlx::query("SELECT \"userId\" FROM \"public\".\"gpu_assets\" WHERE \"machineId\" = $1")
+            .bind(machine_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.map(|r| r.get("userId")))
+    }
+
+    async fn set_machine_owner(&self, machine_id: &str, user_id: &str) -> Result<bool> {
+        let result = sqlx::query("UPDATE \"public\".\"gpu_assets\" SET \"userId\" = $2, \"updatedAt\" = NOW() WHERE \"machineId\" = $1")
+            .bind(machine_id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_client_status(&self, machine_id: &str, status: &str) -> Result<()> {
+        sqlx::query("UPDATE \"public\".\"gpu_assets\" SET status = $2::gpu_asset_status, \"updatedAt\" = NOW() WHERE \"machineId\" = $1")
+            .bind(machine_id)
+            .bind(status)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn owned_machine_ids(&self, user_id: &str) -> Result<HashSet<String>> {
+        let rows = sqlx::query(
+            "SELECT \"machineId\" FROM \"public\".\"gpu_assets\" WHERE \"userId\" = $1 AND status = 'online'"
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(|r| r.get("machineId")).collect())
+    }
+
+    async fn open_client_session(&self, session: NewClientSession<'_>) -> Result<String> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "UPDATE \"public\".\"client_sessions\" SET \"disconnectedAt\" = \"connectedAt\", \"disconnectReason\" = $2 \
+             WHERE \"machineId\" = $1 AND \"disconnectedAt\" IS NULL"
+        )
+        .bind(session.machine_id)
+        .bind(ABANDONED_SESSION)
+        .execute(&mut *tx)
+        .await?;
+
+        let id = Uuid::new_v4().to_string();
+        sqlx::query(
+            "INSERT INTO \"public\".\"client_sessions\" (id, \"machineId\", \"userId\", \"remoteAddr\", \"clientVersion\", \"connectedAt\") \
+             VALUES ($1, $2, $3, $4, $5, NOW())"
+        )
+        .bind(&id)
+        .bind(session.machine_id)
+        .bind(session.user_id)
+        .bind(session.remote_addr)
+        .bind(session.client_version)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(id)
+    }
+
+    async fn close_client_session(&self, session_id: &str, reason: &str) -> Result<()> {
+        sqlx::query(
+            "UPDATE \"public\".\"client_sessions\" SET \"disconnectedAt\" = NOW(), \"disconnectReason\" = $2 \
+             WHERE id = $1 AND \"disconnectedAt\" IS NULL"
+        )
+        .bind(session_id)
+        .bind(reason)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn client_sessions(&self, machine_id: &str, since: DateTime<Utc>) -> Result<Vec<ClientSession>> {
+        let rows = sqlx::query(
+            "SELECT id, \"machineId\", \"userId\", \"remoteAddr\", \"clientVersion\", \"connectedAt\", \"disconnectedAt\", \"disconnectReason\" \
+             FROM \"public\".\"client_sessions\" WHERE \"machineId\" = $1 AND (\"disconnectedAt\" IS NULL OR \"disconnectedAt\" > $2) \
+             ORDER BY \"connectedAt\" DESC"
+        )
+        .bind(machine_id)
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(|r| ClientSession {
+            id: r.get("id"),
+            machine_id: r.get("machineId"),
+            user_id: r.get("userId"),
+            remote_addr: r.get("remoteAddr"),
+            client_version: r.get("clientVersion"),
+            connected_at: r.get("connectedAt"),
+            disconnected_at: r.get("disconnectedAt"),
+            disconnect_reason: r.get("disconnectReason"),
+        }).collect())
+    }
+
+    async fn ban_client(&self, machine_id: &str, until: DateTime<Utc>, reason: Option<&str>) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO "public"."client_bans" ("machineId", "bannedUntil", reason, "createdAt")
+            VALUES ($1, $2, $3, NOW())
+            ON CONFLICT ("machineId")
+            DO UPDATE SET
+                "bannedUntil" = EXCLUDED."bannedUntil",
+                reason = EXCLUDED.reason,
+                "createdAt" = NOW();
+            "#
+        )
+        .bind(machine_id)
+        .bind(until)
+        .bind(reason)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn client_ban(&self, machine_id: &str) -> Result<Option<DateTime<Utc>>> {
+        let row = sqlx::query(
+            "SELECT \"bannedUntil\" FROM \"public\".\"client_bans\" WHERE \"machineId\" = $1 AND \"bannedUntil\" > NOW()"
+        )
+        .bind(machine_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|r| r.get("bannedUntil")))
+    }
+
+    async fn lift_client_ban(&self, machine_id: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM \"public\".\"client_bans\" WHERE \"machineId\" = $1 AND \"bannedUntil\" > NOW()")
+            .bind(machine_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn record_metric_sample(&self, machine_id: &str, sample: &MetricSample) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO \"public\".\"metric_samples\" (\"machineId\", \"recordedAt\", \"cpuUsage\", \"memoryUsage\", \"diskUsage\") VALUES ($1, $2, $3, $4, $5)"
+        )
+        .bind(machine_id)
+        .bind(sample.recorded_at)
+        .bind(sample.cpu_usage)
+        .bind(sample.memory_usage)
+        .bind(sample.disk_usage)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn metric_samples(&self, machine_id: &str, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<MetricSample>> {
+        let rows = sqlx::query(
+            "SELECT \"recordedAt\", \"cpuUsage\", \"memoryUsage\", \"diskUsage\" FROM \"public\".\"metric_samples\" \
+             WHERE \"machineId\" = $1 AND \"recordedAt\" >= $2 AND \"recordedAt\" < $3 ORDER BY \"recordedAt\""
+        )
+        .bind(machine_id)
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(|r| MetricSample {
+            recorded_at: r.get("recordedAt"),
+            cpu_usage: r.get("cpuUsage"),
+            memory_usage: r.get("memoryUsage"),
+            disk_usage: r.get("diskUsage"),
+        }).collect())
+    }
+
+    async fn prune_metric_samples(&self, before: DateTime<Utc>) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM \"public\".\"metric_samples\" WHERE \"recordedAt\" < $1")
+            .bind(before)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn add_traffic(&self, kind: TrafficKind, id: &str, upload_bytes: u64, download_bytes: u64) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO "public"."traffic_totals" (kind, "subjectId", "uploadBytes", "downloadBytes", "updatedAt")
+            VALUES ($1, $2, $3, $4, NOW())
+            ON CONFLICT (kind, "subjectId")
+            DO UPDATE SET
+                "uploadBytes" = "traffic_totals"."uploadBytes" + EXCLUDED."uploadBytes",
+                "downloadBytes" = "traffic_totals"."downloadBytes" + EXCLUDED."downloadBytes",
+                "updatedAt" = NOW();
+            "#
+        )
+        .bind(kind.as_str())
+        .bind(id)
+        .bind(upload_bytes as i64)
+        .bind(download_bytes as i64)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn traffic_totals(&self, kind: TrafficKind) -> Result<Vec<TrafficTotal>> {
+        let rows = sqlx::query(
+            "SELECT \"subjectId\", \"uploadBytes\", \"downloadBytes\" FROM \"public\".\"traffic_totals\" WHERE kind = $1 ORDER BY \"subjectId\""
+        )
+        .bind(kind.as_str())
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|r| TrafficTotal {
+            id: r.get("subjectId"),
+            upload_bytes: r.get::<i64, _>("uploadBytes") as u64,
+            download_bytes: r.get::<i64, _>("downloadBytes") as u64,
+        }).collect())
+    }
+
+    async fn record_audit_entry(&self, principal: &str, role: &str, method: &str, path: &str, status: u16) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO \"public\".\"admin_audit_log\" (principal, role, method, path, status, \"createdAt\") VALUES ($1, $2, $3, $4, $5, NOW())"
+        )
+        .bind(principal)
+        .bind(role)
+        .bind(method)
+        .bind(path)
+        .bind(status as i32)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
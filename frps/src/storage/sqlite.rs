@@ -0,0 +1,610 @@
+use super::{display_prefix, ApiKeyOwner, ApiKeySummary, ClientSession, ClientToken, ClientTokenSummary, MetricSample, NewApiKey, NewClientSession, Storage, TrafficKind, TrafficTotal, User, UserStatus, ABANDONED_SESSION};
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions, SqliteRow};
+use sqlx::{Pool, Row, Sqlite};
+use std::collections::HashSet;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Single-file backend for local development and tests. Timestamps are stored
+/// as RFC 3339 text and compared against bound values rather than SQL `NOW()`.
+pub struct SqliteStorage {
+    pool: Pool<Sqlite>,
+}
+
+impl SqliteStorage {
+    pub async fn connect(url: &str) -> Result<Self> {
+        let options = SqliteConnectOptions::from_str(url)?.create_if_missing(true);
+        let pool = if url.contains(":memory:") || url.contains("mode=memory") {
+            // Every connection to an in-memory database opens a new, empty one, so keep a
+            // single connection alive for the life of the pool
+            SqlitePoolOptions::new()
+                .max_connections(1)
+                .min_connections(1)
+                .idle_timeout(None)
+                .max_lifetime(None)
+                .connect_with(options)
+                .await?
+        } else {
+            SqlitePoolOptions::new()
+                .max_connections(5)
+                .connect_with(options)
+                .await?
+        };
+        Ok(Self { pool })
+    }
+}
+
+const API_KEY_COLUMNS: &str = "id, \"keyHash\", \"keyPrefix\", \"userId\", name, status, tier, \"expiresAt\", \"createdAt\"";
+
+fn api_key_from_row(row: &SqliteRow) -> ApiKeySummary {
+    ApiKeySummary {
+        id: row.get("id"),
+        user_id: row.get("userId"),
+        name: row.get("name"),
+        key_prefix: display_prefix(row.get("keyPrefix")),
+        status: row.get("status"),
+        tier: row.get("tier"),
+        expires_at: row.get("expiresAt"),
+        created_at: row.get("createdAt"),
+        key_hash: row.get("keyHash"),
+    }
+}
+
+const USER_COLUMNS: &str = "id, email, \"passwordHash\", status, \"failedLoginAttempts\", \"createdAt\"";
+
+fn user_from_row(row: &SqliteRow) -> User {
+    User {
+        id: row.get("id"),
+        email: row.get("email"),
+        password_hash: row.get("passwordHash"),
+        status: UserStatus::parse(row.get("status")),
+        failed_login_attempts: row.get("failedLoginAttempts"),
+        created_at: row.get("createdAt"),
+    }
+}
+
+fn is_unique_violation(e: &sqlx::Error) -> bool {
+    e.as_database_error().is_some_and(|db_err| db_err.is_unique_violation())
+}
+
+#[async_trait]
+impl Storage for SqliteStorage {
+    async fn migrate(&self) -> Result<()> {
+        sqlx::migrate!("./migrations/sqlite").run(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn api_key_owner(&self, key_hash: &str) -> Result<Option<ApiKeyOwner>> {
+        let row = sqlx::query(
+            "SELECT id, \"userId\", tier FROM api_keys WHERE \"keyHash\" = ?1 AND status = 'active' AND (\"expiresAt\" IS NULL OR \"expiresAt\" > ?2)"
+        )
+        .bind(key_hash)
+        .bind(Utc::now())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|r| ApiKeyOwner { key_id: r.get("id"), user_id: r.get("userId"), tier: r.get("tier") }))
+    }
+
+    async fn create_api_key(&self, key: NewApiKey<'_>) -> Result<ApiKeySummary> {
+        let row = sqlx::query(&format!(
+            "INSERT INTO api_keys (id, \"keyHash\", \"keyPrefix\", \"userId\", name, status, tier, \"expiresAt\", \"createdAt\", \"updatedAt\") \
+             VALUES (?1, ?2, ?3, ?4, ?5, 'active', ?6, ?7, ?8, ?8) RETURNING {}",
+            API_KEY_COLUMNS
+        ))
+        .bind(Uuid::new_v4().to_string())
+        .bind(key.key_hash)
+        .bind(key.key_prefix)
+        .bind(key.user_id)
+        .bind(key.name)
+        .bind(key.tier)
+        .bind(key.expires_at)
+        .bind(Utc::now())
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(api_key_from_row(&row))
+    }
+
+    async fn list_api_keys(&self, user_id: Option<&str>) -> Result<Vec<ApiKeySummary>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM api_keys WHERE ?1 IS NULL OR \"userId\" = ?1 ORDER BY \"createdAt\" DESC",
+            API_KEY_COLUMNS
+        ))
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(api_key_from_row).collect())
+    }
+
+    async fn rotate_api_key(&self, key_id: &str, key_hash: &str, key_prefix: &str) -> Result<Option<(ApiKeySummary, Option<String>)>> {
+        let mut tx = self.pool.begin().await?;
+
+        let old_key_hash: Option<Option<String>> = sqlx::query_scalar(
+            "SELECT \"keyHash\" FROM api_keys WHERE id = ?1 AND status = 'active'"
+        )
+        .bind(key_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(old_key_hash) = old_key_hash else {
+            return Ok(None);
+        };
+
+        let row = sqlx::query(&format!(
+            "UPDATE api_keys SET \"keyHash\" = ?2, \"keyPrefix\" = ?3, \"updatedAt\" = ?4 WHERE id = ?1 RETURNING {}",
+            API_KEY_COLUMNS
+        ))
+        .bind(key_id)
+        .bind(key_hash)
+        .bind(key_prefix)
+        .bind(Utc::now())
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(Some((api_key_from_row(&row), old_key_hash)))
+    }
+
+    async fn set_api_key_expiry(&self, key_id: &str, expires_at: Option<DateTime<Utc>>) -> Result<Option<ApiKeySummary>> {
+        let row = sqlx::query(&format!(
+            "UPDATE api_keys SET \"expiresAt\" = ?2, \"updatedAt\" = ?3 WHERE id = ?1 RETURNING {}",
+            API_KEY_COLUMNS
+        ))
+        .bind(key_id)
+        .bind(expires_at)
+        .bind(Utc::now())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.as_ref().map(api_key_from_row))
+    }
+
+    async fn set_api_key_tier(&self, key_id: &str, tier: Option<&str>) -> Result<Option<ApiKeySummary>> {
+        let row = sqlx::query(&format!(
+            "UPDATE api_keys SET tier = ?2, \"updatedAt\" = ?3 WHERE id = ?1 RETURNING {}",
+            API_KEY_COLUMNS
+        ))
+        .bind(key_id)
+        .bind(tier)
+        .bind(Utc::now())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.as_ref().map(api_key_from_row))
+    }
+
+    async fn revoke_api_key(&self, key_id: &str) -> Result<Option<ApiKeySummary>> {
+        let row = sqlx::query(&format!(
+            "UPDATE api_keys SET status = 'revoked', \"updatedAt\" = ?2 WHERE id = ?1 RETURNING {}",
+            API_KEY_COLUMNS
+        ))
+        .bind(key_id)
+        .bind(Utc::now())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.as_ref().map(api_key_from_row))
+    }
+
+    async fn insert_client_token(&self, token_hash: &str, token_prefix: &str, user_id: &str, expires_at: DateTime<Utc>) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO client_tokens (id, \"tokenHash\", \"tokenPrefix\", \"userId\", \"expiresAt\", \"createdAt\") VALUES (?1, ?2, ?3, ?4, ?5, ?6)"
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(token_hash)
+        .bind(token_prefix)
+        .bind(user_id)
+        .bind(expires_at)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn find_client_token(&self, token_hash: &str) -> Result<Option<ClientToken>> {
+        let row = sqlx::query(
+            "SELECT t.id, t.\"userId\", t.\"expiresAt\" FROM client_tokens t \
+             JOIN users u ON u.id = t.\"userId\" \
+             WHERE t.\"tokenHash\" = ?1 AND t.\"revokedAt\" IS NULL AND t.\"expiresAt\" > ?2 AND u.status = 'active'"
+        )
+        .bind(token_hash)
+        .bind(Utc::now())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|r| ClientToken {
+            id: r.get("id"),
+            user_id: r.get("userId"),
+            expires_at: r.get("expiresAt"),
+        }))
+    }
+
+    async fn revoke_client_token(&self, token_id: &str) -> Result<Vec<Option<String>>> {
+        let rows = sqlx::query(
+            "UPDATE client_tokens SET \"revokedAt\" = ?2 WHERE id = ?1 AND \"revokedAt\" IS NULL RETURNING \"tokenHash\""
+        )
+        .bind(token_id)
+        .bind(Utc::now())
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(|r| r.get("tokenHash")).collect())
+    }
+
+    async fn revoke_user_client_tokens(&self, user_id: &str) -> Result<Vec<Option<String>>> {
+        let rows = sqlx::query(
+            "UPDATE client_tokens SET \"revokedAt\" = ?2 WHERE \"userId\" = ?1 AND \"revokedAt\" IS NULL RETURNING \"tokenHash\""
+        )
+        .bind(user_id)
+        .bind(Utc::now())
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(|r| r.get("tokenHash")).collect())
+    }
+
+    async fn list_active_client_tokens(&self) -> Result<Vec<ClientTokenSummary>> {
+        let rows = sqlx::query(
+            "SELECT id, \"tokenPrefix\", \"userId\", \"createdAt\", \"expiresAt\" FROM client_tokens \
+             WHERE \"revokedAt\" IS NULL AND \"expiresAt\" > ?1 ORDER BY \"createdAt\" DESC"
+        )
+        .bind(Utc::now())
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(|r| ClientTokenSummary {
+            id: r.get("id"),
+            user_id: r.get("userId"),
+            token_prefix: display_prefix(r.get("tokenPrefix")),
+            created_at: r.get("createdAt"),
+            expires_at: r.get("expiresAt"),
+        }).collect())
+    }
+
+    async fn find_user_by_email(&self, email: &str) -> Result<Option<User>> {
+        let row = sqlx::query(&format!("SELECT {} FROM users WHERE email = ?1", USER_COLUMNS))
+            .bind(email)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.as_ref().map(user_from_row))
+    }
+
+    async fn list_users(&self) -> Result<Vec<User>> {
+        let rows = sqlx::query(&format!("SELECT {} FROM users ORDER BY \"createdAt\"", USER_COLUMNS))
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.iter().map(user_from_row).collect())
+    }
+
+    async fn create_user(&self, id: &str, email: &str, password_hash: &str) -> Result<Option<User>> {
+        let result = sqlx::query(&format!(
+            "INSERT INTO users (id, email, \"passwordHash\", status, \"failedLoginAttempts\", \"createdAt\", \"updatedAt\") \
+             VALUES (?1, ?2, ?3, 'active', 0, ?4, ?4) RETURNING {}",
+            USER_COLUMNS
+        ))
+        .bind(id)
+        .bind(email)
+        .bind(password_hash)
+        .bind(Utc::now())
+        .fetch_one(&self.pool)
+        .await;
+
+        match result {
+            Ok(row) => Ok(Some(user_from_row(&row))),
+            Err(e) if is_unique_violation(&e) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn set_user_status(&self, user_id: &str, status: UserStatus) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE users SET status = ?2, \"failedLoginAttempts\" = 0, \"updatedAt\" = ?3 WHERE id = ?1"
+        )
+        .bind(user_id)
+        .bind(status.as_str())
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_user_password(&self, user_id: &str, password_hash: &str) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE users SET \"passwordHash\" = ?2, \"failedLoginAttempts\" = 0, \
+             status = CASE WHEN status = 'locked' THEN 'active' ELSE status END, \"updatedAt\" = ?3 WHERE id = ?1"
+        )
+        .bind(user_id)
+        .bind(password_hash)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn record_failed_login(&self, user_id: &str, max_attempts: i32) -> Result<()> {
+        sqlx::query(
+            "UPDATE users SET \"failedLoginAttempts\" = \"failedLoginAttempts\" + 1, \
+             status = CASE WHEN \"failedLoginAttempts\" + 1 >= ?2 THEN 'locked' ELSE status END, \"updatedAt\" = ?3 WHERE id = ?1"
+        )
+        .bind(user_id)
+        .bind(max_attempts)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn reset_failed_logins(&self, user_id: &str) -> Result<()> {
+        sqlx::query("UPDATE users SET \"failedLoginAttempts\" = 0 WHERE id = ?1")
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn upsert_client_info(&self, user_id: &str, machine_id: &str, name: &str, status: &str) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO gpu_assets ("userId", "machineId", "name", "status", "createdAt", "updatedAt")
+            VALUES (?1, ?2, ?3, ?4, ?5, ?5)
+            ON CONFLICT ("machineId")
+            DO UPDATE SET
+                "name" = excluded."name",
+                "status" = excluded."status",
+                "updatedAt" = excluded."updatedAt"
+            WHERE gpu_assets."userId" = excluded."userId";
+            "#
+        )
+        .bind(user_id)
+        .bind(machine_id)
+        .bind(name)
+        .bind(status)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn machine_owner(&self, machine_id: &str) -> Result<Option<String>> {
+        let row = sqlx::query("SELECT \"userId\" FROM gpu_assets WHERE \"machineId\" = ?1")
+            .bind(machine_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.map(|r| r.get("userId")))
+    }
+
+    async fn set_machine_owner(&self, machine_id: &str, user_id: &str) -> Result<bool> {
+        let result = sqlx::query("UPDATE gpu_assets SET \"userId\" = ?2, \"updatedAt\" = ?3 WHERE \"machineId\" = ?1")
+            .bind(machine_id)
+            .bind(user_id)
+            .bind(Utc::now())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_client_status(&self, machine_id: &str, status: &str) -> Result<()> {
+        sqlx::query("UPDATE gpu_assets SET status = ?2, \"updatedAt\" = ?3 WHERE \"machineId\" = ?1")
+            .bind(machine_id)
+            .bind(status)
+            .bind(Utc::now())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn owned_machine_ids(&self, user_id: &str) -> Result<HashSet<String>> {
+        let rows = sqlx::query("SELECT \"machineId\" FROM gpu_assets WHERE \"userId\" = ?1 AND status = 'online'")
+            .bind(user_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.iter().map(|r| r.get("machineId")).collect())
+    }
+
+    async fn open_client_session(&self, session: NewClientSession<'_>) -> Result<String> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "UPDATE client_sessions SET \"disconnectedAt\" = \"connectedAt\", \"disconnectReason\" = ?2 \
+             WHERE \"machineId\" = ?1 AND \"disconnectedAt\" IS NULL"
+        )
+        .bind(session.machine_id)
+        .bind(ABANDONED_SESSION)
+        .execute(&mut *tx)
+        .await?;
+
+        let id = Uuid::new_v4().to_string();
+        sqlx::query(
+            "INSERT INTO client_sessions (id, \"machineId\", \"userId\", \"remoteAddr\", \"clientVersion\", \"connectedAt\") \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)"
+        )
+        .bind(&id)
+        .bind(session.machine_id)
+        .bind(session.user_id)
+        .bind(session.remote_addr)
+        .bind(session.client_version)
+        .bind(Utc::now())
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(id)
+    }
+
+    async fn close_client_session(&self, session_id: &str, reason: &str) -> Result<()> {
+        sqlx::query(
+            "UPDATE client_sessions SET \"disconnectedAt\" = ?3, \"disconnectReason\" = ?2 \
+             WHERE id = ?1 AND \"disconnectedAt\" IS NULL"
+        )
+        .bind(session_id)
+        .bind(reason)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn client_sessions(&self, machine_id: &str, since: DateTime<Utc>) -> Result<Vec<ClientSession>> {
+        let rows = sqlx::query(
+            "SELECT id, \"machineId\", \"userId\", \"remoteAddr\", \"clientVersion\", \"connectedAt\", \"disconnectedAt\", \"disconnectReason\" \
+             FROM client_sessions WHERE \"machineId\" = ?1 AND (\"disconnectedAt\" IS NULL OR \"disconnectedAt\" > ?2) \
+             ORDER BY \"connectedAt\" DESC"
+        )
+        .bind(machine_id)
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(|r| ClientSession {
+            id: r.get("id"),
+            machine_id: r.get("machineId"),
+            user_id: r.get("userId"),
+            remote_addr: r.get("remoteAddr"),
+            client_version: r.get("clientVersion"),
+            connected_at: r.get("connectedAt"),
+            disconnected_at: r.get("disconnectedAt"),
+            disconnect_reason: r.get("disconnectReason"),
+        }).collect())
+    }
+
+    async fn ban_client(&self, machine_id: &str, until: DateTime<Utc>, reason: Option<&str>) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO client_bans (\"machineId\", \"bannedUntil\", reason, \"createdAt\") VALUES (?1, ?2, ?3, ?4) \
+             ON CONFLICT (\"machineId\") DO UPDATE SET \"bannedUntil\" = excluded.\"bannedUntil\", reason = excluded.reason, \"createdAt\" = excluded.\"createdAt\""
+        )
+        .bind(machine_id)
+        .bind(until)
+        .bind(reason)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn client_ban(&self, machine_id: &str) -> Result<Option<DateTime<Utc>>> {
+        let row = sqlx::query("SELECT \"bannedUntil\" FROM client_bans WHERE \"machineId\" = ?1 AND \"bannedUntil\" > ?2")
+            .bind(machine_id)
+            .bind(Utc::now())
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.map(|r| r.get("bannedUntil")))
+    }
+
+    async fn lift_client_ban(&self, machine_id: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM client_bans WHERE \"machineId\" = ?1 AND \"bannedUntil\" > ?2")
+            .bind(machine_id)
+            .bind(Utc::now())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn record_metric_sample(&self, machine_id: &str, sample: &MetricSample) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO metric_samples (\"machineId\", \"recordedAt\", \"cpuUsage\", \"memoryUsage\", \"diskUsage\") VALUES (?1, ?2, ?3, ?4, ?5)"
+        )
+        .bind(machine_id)
+        .bind(sample.recorded_at)
+        .bind(sample.cpu_usage)
+        .bind(sample.memory_usage)
+        .bind(sample.disk_usage)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn metric_samples(&self, machine_id: &str, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<MetricSample>> {
+        let rows = sqlx::query(
+            "SELECT \"recordedAt\", \"cpuUsage\", \"memoryUsage\", \"diskUsage\" FROM metric_samples \
+             WHERE \"machineId\" = ?1 AND \"recordedAt\" >= ?2 AND \"recordedAt\" < ?3 ORDER BY \"recordedAt\""
+        )
+        .bind(machine_id)
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(|r| MetricSample {
+            recorded_at: r.get("recordedAt"),
+            cpu_usage: r.get("cpuUsage"),
+            memory_usage: r.get("memoryUsage"),
+            disk_usage: r.get("diskUsage"),
+        }).collect())
+    }
+
+    async fn prune_metric_samples(&self, before: DateTime<Utc>) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM metric_samples WHERE \"recordedAt\" < ?1")
+            .bind(before)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn add_traffic(&self, kind: TrafficKind, id: &str, upload_bytes: u64, download_bytes: u64) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO traffic_totals (kind, \"subjectId\", \"uploadBytes\", \"downloadBytes\", \"updatedAt\") VALUES (?1, ?2, ?3, ?4, ?5) \
+             ON CONFLICT (kind, \"subjectId\") DO UPDATE SET \"uploadBytes\" = \"uploadBytes\" + excluded.\"uploadBytes\", \
+             \"downloadBytes\" = \"downloadBytes\" + excluded.\"downloadBytes\", \"updatedAt\" = excluded.\"updatedAt\""
+        )
+        .bind(kind.as_str())
+        .bind(id)
+        .bind(upload_bytes as i64)
+        .bind(download_bytes as i64)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn traffic_totals(&self, kind: TrafficKind) -> Result<Vec<TrafficTotal>> {
+        let rows = sqlx::query("SELECT \"subjectId\", \"uploadBytes\", \"downloadBytes\" FROM traffic_totals WHERE kind = ?1 ORDER BY \"subjectId\"")
+            .bind(kind.as_str())
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.into_iter().map(|r| TrafficTotal {
+            id: r.get("subjectId"),
+            upload_bytes: r.get::<i64, _>("uploadBytes") as u64,
+            download_bytes: r.get::<i64, _>("downloadBytes") as u64,
+        }).collect())
+    }
+
+    async fn record_audit_entry(&self, principal: &str, role: &str, method: &str, path: &str, status: u16) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO admin_audit_log (principal, role, method, path, status, \"createdAt\") VALUES (?1, ?2, ?3, ?4, ?5, ?6)"
+        )
+        .bind(principal)
+        .bind(role)
+        .bind(method)
+        .bind(path)
+        .bind(status as i32)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -5,7 +5,7 @@
 echo "Testing API key validation with JSON error responses..."
 
 # Start the server with default API key in background
-./target/release/frps --api-key "test123" &
+./target/release/frps --api-key "test123" --key-hash-secret "test-hash-secret" &
 SERVER_PID=$!
 
 # Wait for server to start
@@ -8,6 +8,9 @@ TEST_CONTROL_PORT=17000
 TEST_PROXY_PORT=17001
 TEST_PUBLIC_PORT=18080
 TEST_LOCAL_PORT=11434
+TEST_ADMIN_KEY="test-admin-key"
+# In-memory storage by default; set FRPS_DATABASE_URL to test against Postgres or SQLite
+FRPS_DATABASE_URL="${FRPS_DATABASE_URL:-memory://}"
 
 FRPS_LOG="frps.log"
 FRPC_A_LOG="frpc_a.log"
@@ -91,12 +94,27 @@ python3 -m http.server ${TEST_LOCAL_PORT} &> $HTTP_SERVER_LOG &
 HTTP_PID=$!
 echo "- Local HTTP server started (PID: $HTTP_PID)"
 
-./target/release/frps --control-port ${TEST_CONTROL_PORT} --proxy-port ${TEST_PROXY_PORT} --public-port ${TEST_PUBLIC_PORT} &> $FRPS_LOG &
+./target/release/frps --control-port ${TEST_CONTROL_PORT} --proxy-port ${TEST_PROXY_PORT} --public-port ${TEST_PUBLIC_PORT} --database-url ${FRPS_DATABASE_URL} --auto-migrate --admin-key ${TEST_ADMIN_KEY} --key-hash-secret test-hash-secret &> $FRPS_LOG &
 FRPS_PID=$!
 echo "- frps server started (PID: $FRPS_PID)"
 
 wait_for_log $FRPS_LOG "FRPS listening on ports" $WAIT_TIMEOUT || exit 1
 
+# Make sure the test account exists (a 409 means it was created by an earlier run)
+curl -s -o /dev/null -X POST http://localhost:18081/api/users \
+  -H "Authorization: Bearer ${TEST_ADMIN_KEY}" \
+  -H "Content-Type: application/json" \
+  -d '{"email": "test@example.com", "password": "123456"}'
+
+# Issue an API key for the test account to authenticate the public requests
+TEST_USER_ID=$(curl -s http://localhost:18081/api/users -H "Authorization: Bearer ${TEST_ADMIN_KEY}" \
+  | python3 -c 'import json,sys; print(next(u["id"] for u in json.load(sys.stdin)["data"] if u["email"] == "test@example.com"))')
+TEST_API_KEY=$(curl -s -X POST http://localhost:18081/api/keys \
+  -H "Authorization: Bearer ${TEST_ADMIN_KEY}" \
+  -H "Content-Type: application/json" \
+  -d "{\"user_id\": \"${TEST_USER_ID}\", \"name\": \"test_frpx\"}" \
+  | python3 -c 'import json,sys; print(json.load(sys.stdin)["data"]["key"])')
+
 ./target/release/frpc --client-id client_A --control-port ${TEST_CONTROL_PORT} --proxy-port ${TEST_PROXY_PORT} --local-port ${TEST_LOCAL_PORT} --email test@example.com --password 123456 &> $FRPC_A_LOG &
 FRPC_A_PID=$!
 echo "- frpc client_A started (PID: $FRPC_A_PID)"
@@ -117,7 +135,7 @@ echo "
 [STEP 4] Performing ${REQUEST_COUNT} test requests..."
 SUCCESSFUL_REQUESTS=0
 for i in $(seq 1 $REQUEST_COUNT); do
-    HTTP_STATUS=$(curl --max-time 5 -s -o /dev/null -w '%{http_code}' http://localhost:${TEST_PUBLIC_PORT})
+    HTTP_STATUS=$(curl --max-time 5 -s -o /dev/null -w '%{http_code}' -H "Authorization: Bearer ${TEST_API_KEY}" http://localhost:${TEST_PUBLIC_PORT})
     if [ "$HTTP_STATUS" -eq 200 ]; then
         echo "- Request $i: OK (Status: $HTTP_STATUS)"
         SUCCESSFUL_REQUESTS=$((SUCCESSFUL_REQUESTS + 1))